@@ -0,0 +1,31 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_input;
+layout(set=0, binding=1) uniform sampler s_input;
+layout(set=0, binding=2) uniform texture2D t_aux;
+layout(set=0, binding=3) uniform sampler s_aux;
+
+layout(set=0, binding=4)
+uniform PostParams {
+    vec4 u_params;
+};
+
+// u_params.x: radius in texels, u_params.yz: blur direction
+
+const int TAPS = 5;
+const float WEIGHTS[TAPS] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2D(t_input, s_input), 0));
+    vec2 offset = u_params.yz * u_params.x * texel_size;
+
+    vec3 color = texture(sampler2D(t_input, s_input), v_tex_coords).rgb * WEIGHTS[0];
+    for (int i = 1; i < TAPS; ++i) {
+        color += texture(sampler2D(t_input, s_input), v_tex_coords + offset * float(i)).rgb * WEIGHTS[i];
+        color += texture(sampler2D(t_input, s_input), v_tex_coords - offset * float(i)).rgb * WEIGHTS[i];
+    }
+    f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_input;
+layout(set=0, binding=1) uniform sampler s_input;
+layout(set=0, binding=2) uniform texture2D t_aux;
+layout(set=0, binding=3) uniform sampler s_aux;
+
+layout(set=0, binding=4)
+uniform PostParams {
+    vec4 u_params;
+};
+
+// u_params.x: intensity, t_aux: blurred bloom
+
+void main() {
+    vec3 color = texture(sampler2D(t_input, s_input), v_tex_coords).rgb;
+    vec3 bloom = texture(sampler2D(t_aux, s_aux), v_tex_coords).rgb;
+    f_color = vec4(color + bloom * u_params.x, 1.0);
+}
@@ -0,0 +1,31 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_input;
+layout(set=0, binding=1) uniform sampler s_input;
+layout(set=0, binding=2) uniform texture2D t_aux;
+layout(set=0, binding=3) uniform sampler s_aux;
+
+layout(set=0, binding=4)
+uniform PostParams {
+    vec4 u_params;
+};
+
+// u_params.x: threshold
+
+void main() {
+    // Four bilinear taps average a 4x4 texel area of the full resolution input.
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2D(t_input, s_input), 0));
+    vec3 color = vec3(0.0);
+    color += texture(sampler2D(t_input, s_input), v_tex_coords + texel_size * vec2(-1.0, -1.0)).rgb;
+    color += texture(sampler2D(t_input, s_input), v_tex_coords + texel_size * vec2(1.0, -1.0)).rgb;
+    color += texture(sampler2D(t_input, s_input), v_tex_coords + texel_size * vec2(-1.0, 1.0)).rgb;
+    color += texture(sampler2D(t_input, s_input), v_tex_coords + texel_size * vec2(1.0, 1.0)).rgb;
+    color *= 0.25;
+
+    float brightness = max(color.r, max(color.g, color.b));
+    float contribution = max(brightness - u_params.x, 0.0) / max(brightness, 0.0001);
+    f_color = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,44 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_input;
+layout(set=0, binding=1) uniform sampler s_input;
+layout(set=0, binding=2) uniform texture2D t_aux;
+layout(set=0, binding=3) uniform sampler s_aux;
+
+layout(set=0, binding=4)
+uniform PostParams {
+    vec4 u_params;
+};
+
+// u_params.x: strength, t_aux: LUT strip of N slices of NxN texels, blue along the slices
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+vec3 sample_slice(vec2 rg, float slice, float size) {
+    vec2 uv = vec2((slice * size + rg.x * (size - 1.0) + 0.5) / (size * size),
+                   (rg.y * (size - 1.0) + 0.5) / size);
+    return texture(sampler2D(t_aux, s_aux), uv).rgb;
+}
+
+void main() {
+    vec3 color = texture(sampler2D(t_input, s_input), v_tex_coords).rgb;
+    float size = float(textureSize(sampler2D(t_aux, s_aux), 0).y);
+
+    // The LUT is sRGB encoded, the sampler decodes its output back to linear.
+    vec3 coords = linear_to_srgb(clamp(color, 0.0, 1.0));
+    float blue = coords.b * (size - 1.0);
+    float slice = floor(blue);
+    vec3 graded = mix(sample_slice(coords.rg, slice, size),
+                      sample_slice(coords.rg, min(slice + 1.0, size - 1.0), size),
+                      blue - slice);
+    graded += max(color - 1.0, 0.0);
+
+    f_color = vec4(mix(color, graded, u_params.x), 1.0);
+}
@@ -0,0 +1,84 @@
+#version 450
+
+layout(location=0) in vec4 v_color;
+layout(location=1) in vec3 v_world_position;
+layout(location=2) in vec3 v_normal;
+layout(location=3) in vec2 v_tex_coords;
+layout(location=4) flat in uint v_layer;
+layout(location=0) out vec4 f_color;
+
+#include "include/uniforms.glsl"
+#include "include/lights.glsl"
+#include "include/shadow.glsl"
+#include "include/fog.glsl"
+#include "include/material.glsl"
+
+const vec3 TRANSLUCENCY_COLOR = vec3(0.6, 0.9, 0.2);
+const float TRANSLUCENCY_STRENGTH = 0.6;
+const float TRANSLUCENCY_POWER = 4.0;
+const float SPECULAR_STRENGTH = 0.3;
+const float SPECULAR_POWER = 16.0;
+// Occlusion at the root of a blade, fading to none at the tip.
+const float ROOT_OCCLUSION = 0.25;
+const float OCCLUSION_EXPONENT = 0.6;
+
+vec3 grass_lighting(vec3 normal, vec3 view_dir, vec3 light_dir, vec3 light_color) {
+    float diffuse_strength = max(dot(normal, light_dir), 0.0);
+    vec3 half_dir = normalize(view_dir + light_dir);
+    float specular_strength = pow(max(dot(normal, half_dir), 0.0), SPECULAR_POWER)
+        * SPECULAR_STRENGTH * step(0.0, dot(normal, light_dir));
+
+    // Light passing through the blade: strongest when it hits the back face and when looking
+    // towards the light.
+    float back_lit = max(dot(-normal, light_dir), 0.0);
+    float forward_scatter = pow(max(dot(view_dir, -light_dir), 0.0), TRANSLUCENCY_POWER);
+    vec3 translucency = TRANSLUCENCY_COLOR * TRANSLUCENCY_STRENGTH
+        * (back_lit + forward_scatter);
+
+    return light_color * (diffuse_strength + specular_strength + translucency);
+}
+
+// Alpha to output for the material's alpha mode. Discards cut out fragments.
+float material_alpha(float alpha) {
+    if (u_alpha_mode == ALPHA_MODE_BLEND) {
+        return alpha;
+    }
+    if (u_alpha_mode == ALPHA_MODE_COVERAGE) {
+        // Sharpened around the cutoff, so coverage antialiases the edge instead of dithering
+        // the whole texture.
+        alpha = (alpha - u_alpha_cutoff) / max(fwidth(alpha), 0.0001) + 0.5;
+        if (alpha <= 0.0) {
+            discard;
+        }
+        return clamp(alpha, 0.0, 1.0);
+    }
+    if (u_alpha_mode == ALPHA_MODE_MASK && alpha < u_alpha_cutoff) {
+        discard;
+    }
+    return 1.0;
+}
+
+void main() {
+    // Blades are rendered without culling, so back faces need the flipped normal.
+    vec3 normal = normalize(gl_FrontFacing ? v_normal : -v_normal);
+    vec3 view_dir = normalize(u_view_position.xyz - v_world_position);
+    float occlusion = mix(ROOT_OCCLUSION, 1.0, pow(clamp(v_tex_coords.y, 0.0, 1.0), OCCLUSION_EXPONENT));
+
+    vec3 luminance = u_ambient_color.rgb * occlusion;
+    luminance += grass_lighting(normal, view_dir, normalize(u_sun_direction.xyz), u_sun_color.rgb)
+        * shadow(v_world_position) * occlusion;
+
+    for (uint i = 0; i < u_point_light_count; ++i) {
+        PointLight light = u_point_lights[i];
+        vec3 to_light = light.position_range.xyz - v_world_position;
+        float distance = length(to_light);
+        float falloff = clamp(1.0 - distance / light.position_range.w, 0.0, 1.0);
+        luminance += grass_lighting(normal, view_dir, to_light / distance, light.color.rgb)
+            * falloff * falloff * occlusion;
+    }
+
+    // Blade texture coordinates go from the root up, images are stored top row first.
+    vec4 albedo = v_color
+        * texture(sampler2DArray(t_albedo, s_albedo), vec3(v_tex_coords.x, 1.0 - v_tex_coords.y, float(v_layer)));
+    f_color = vec4(apply_fog(albedo.rgb * luminance, v_world_position), material_alpha(albedo.a));
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout(location=3) in vec2 v_tex_coords;
+layout(location=4) flat in uint v_layer;
+
+#include "include/material.glsl"
+
+// Only depth is written, cut out texels are left out of the shadow map.
+void main() {
+    float alpha = texture(sampler2DArray(t_albedo, s_albedo), vec3(v_tex_coords.x, 1.0 - v_tex_coords.y, float(v_layer))).a;
+    if (alpha < u_alpha_cutoff) {
+        discard;
+    }
+}
@@ -0,0 +1,23 @@
+#ifndef FOG_GLSL
+#define FOG_GLSL
+
+#include "include/uniforms.glsl"
+
+// Exponential height fog integrated along the view ray, starting at the fog start distance.
+vec3 apply_fog(vec3 color, vec3 world_position) {
+    vec3 ray = world_position - u_view_position.xyz;
+    float ray_length = max(length(ray), 0.0001);
+    float fog_length = max(ray_length - u_fog_params.w, 0.0);
+    float falloff = u_fog_params.x;
+    float start_height = u_view_position.y + ray.y / ray_length * u_fog_params.w - u_fog_params.y;
+
+    float optical_depth = u_fog_color.a * exp(-falloff * start_height) * fog_length;
+    float height_change = falloff * ray.y / ray_length * fog_length;
+    if (abs(height_change) > 0.0001) {
+        optical_depth *= (1.0 - exp(-height_change)) / height_change;
+    }
+    float opacity = min(1.0 - exp(-optical_depth), u_fog_params.z);
+    return mix(color, u_fog_color.rgb, opacity);
+}
+
+#endif
@@ -0,0 +1,21 @@
+#ifndef LIGHTS_GLSL
+#define LIGHTS_GLSL
+
+const int MAX_POINT_LIGHTS = 8;
+
+struct PointLight {
+    vec4 position_range;
+    vec4 color;
+};
+
+// Colors are premultiplied by intensity.
+layout(set=0, binding=3)
+uniform Lights {
+    vec4 u_sun_direction;
+    vec4 u_sun_color;
+    vec4 u_ambient_color;
+    PointLight u_point_lights[MAX_POINT_LIGHTS];
+    uint u_point_light_count;
+};
+
+#endif
@@ -0,0 +1,19 @@
+#ifndef MATERIAL_GLSL
+#define MATERIAL_GLSL
+
+layout(set=1, binding=0) uniform texture2DArray t_albedo;
+layout(set=1, binding=1) uniform sampler s_albedo;
+
+// Matches `MaterialUniform` in material.rs.
+layout(set=1, binding=2)
+uniform Material {
+    float u_alpha_cutoff;
+    uint u_alpha_mode;
+};
+
+const uint ALPHA_MODE_OPAQUE = 0;
+const uint ALPHA_MODE_MASK = 1;
+const uint ALPHA_MODE_COVERAGE = 2;
+const uint ALPHA_MODE_BLEND = 3;
+
+#endif
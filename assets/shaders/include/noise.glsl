@@ -0,0 +1,29 @@
+#ifndef NOISE_GLSL
+#define NOISE_GLSL
+
+// https://www.shadertoy.com/view/XdXBRH
+vec2 hash( in vec2 x )  // replace this by something better
+{
+    const vec2 k = vec2( 0.3183099, 0.3678794 );
+    x = x*k + k.yx;
+    return fract( 16.0 * k*fract( x.x*x.y*(x.x+x.y)) );
+}
+
+// https://en.wikipedia.org/wiki/Worley_noise
+float worley(vec2 p) {
+
+    vec2 i_p = floor(p);
+    vec2 f_p = fract(p);
+
+    float dist = 1.0;
+    for(int y = -1;y <= 1;++y) {
+        for(int x = -1;x <= 1;++x) {
+            vec2 n = vec2(float(x), float(y));
+            vec2 diff = n + hash(i_p + n) - f_p;
+            dist = min(dist, length(diff));
+        }
+    }
+    return dist;
+}
+
+#endif
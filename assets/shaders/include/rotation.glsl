@@ -0,0 +1,20 @@
+#ifndef ROTATION_GLSL
+#define ROTATION_GLSL
+
+// https://en.wikipedia.org/wiki/Rodrigues'_rotation_formula
+mat3 mat3_from_axis_angle(float angle, vec3 axis) {
+	float s = sin(angle);
+	float c = cos(angle);
+	float t = 1.0 - c;
+	float x = axis.x;
+	float y = axis.y;
+	float z = axis.z;
+    // Matrix: v * c + cross(axis,v) * s + axis*dot(axis,v) * t
+	return mat3(
+		vec3(t*x*x+c,t*x*y-s*z,t*x*z+s*y),
+		vec3(t*x*y+s*z,t*y*y+c,t*y*z-s*x),
+		vec3(t*x*z-s*y,t*y*z+s*z,t*z*z+c)
+	);
+}
+
+#endif
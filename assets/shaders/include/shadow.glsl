@@ -0,0 +1,37 @@
+#ifndef SHADOW_GLSL
+#define SHADOW_GLSL
+
+#include "include/uniforms.glsl"
+
+layout(set=0, binding=1) uniform texture2D t_shadow;
+layout(set=0, binding=2) uniform samplerShadow s_shadow;
+
+const int PCF_RADIUS = 1;
+
+// Fraction of light reaching the fragment, filtered over a (2 * PCF_RADIUS + 1)^2 texel area.
+// Everything is lit when compiled without SHADOWS.
+float shadow(vec3 world_position) {
+#ifndef SHADOWS
+    return 1.0;
+#else
+    vec4 light_position = u_light_view_proj * vec4(world_position, 1.0);
+    vec3 ndc = light_position.xyz / light_position.w;
+    if (ndc.z > 1.0) {
+        return 1.0;
+    }
+    vec2 uv = ndc.xy * vec2(0.5, -0.5) + 0.5;
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2DShadow(t_shadow, s_shadow), 0));
+
+    float lit = 0.0;
+    for (int y = -PCF_RADIUS; y <= PCF_RADIUS; ++y) {
+        for (int x = -PCF_RADIUS; x <= PCF_RADIUS; ++x) {
+            vec2 offset = vec2(float(x), float(y)) * texel_size;
+            lit += texture(sampler2DShadow(t_shadow, s_shadow), vec3(uv + offset, ndc.z));
+        }
+    }
+    float taps = float((2 * PCF_RADIUS + 1) * (2 * PCF_RADIUS + 1));
+    return lit / taps;
+#endif
+}
+
+#endif
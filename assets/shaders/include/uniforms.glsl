@@ -0,0 +1,17 @@
+#ifndef UNIFORMS_GLSL
+#define UNIFORMS_GLSL
+
+// Matches `Uniforms` in state.rs.
+layout(set=0, binding=0)
+uniform Uniforms {
+    mat4 u_view_proj;
+    mat4 u_light_view_proj;
+    vec4 u_view_position;
+    float u_time;
+    float u_season;
+    mat4 u_inverse_view_proj;
+    vec4 u_fog_color; // rgb: color, a: density
+    vec4 u_fog_params; // x: height falloff, y: base height, z: max opacity, w: start distance
+};
+
+#endif
@@ -0,0 +1,13 @@
+// Error text over a translucent background. The red channel of the text texture is the glyph
+// coverage.
+[[location(0)]] var<in> v_tex_coords: vec2<f32>;
+[[location(0)]] var<out> f_color: vec4<f32>;
+
+[[group(0), binding(1)]] var t_text: texture_2d<f32>;
+[[group(0), binding(2)]] var s_text: sampler;
+
+[[stage(fragment)]]
+fn main() {
+    const coverage: f32 = textureSample(t_text, s_text, v_tex_coords).x;
+    f_color = mix(vec4<f32>(0.05, 0.0, 0.0, 0.8), vec4<f32>(1.0, 0.55, 0.5, 1.0), coverage);
+}
@@ -0,0 +1,24 @@
+// A quad over `rect`, drawn as a triangle strip of 4 vertices without a vertex buffer.
+[[block]]
+struct Overlay {
+    // Left, top, right and bottom edge in clip space.
+    rect: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> u_overlay: Overlay;
+
+[[builtin(vertex_index)]] var<in> in_vertex_index: u32;
+[[builtin(position)]] var<out> out_position: vec4<f32>;
+[[location(0)]] var<out> v_tex_coords: vec2<f32>;
+
+[[stage(vertex)]]
+fn main() {
+    const corner: vec2<f32> = vec2<f32>(f32(in_vertex_index & 1u), f32(in_vertex_index >> 1u));
+    v_tex_coords = corner;
+    out_position = vec4<f32>(
+        mix(u_overlay.rect.x, u_overlay.rect.z, corner.x),
+        mix(u_overlay.rect.y, u_overlay.rect.w, corner.y),
+        0.0,
+        1.0
+    );
+}
@@ -0,0 +1,11 @@
+// Copies the input unchanged.
+[[location(0)]] var<in> v_tex_coords: vec2<f32>;
+[[location(0)]] var<out> f_color: vec4<f32>;
+
+[[group(0), binding(0)]] var t_input: texture_2d<f32>;
+[[group(0), binding(1)]] var s_input: sampler;
+
+[[stage(fragment)]]
+fn main() {
+    f_color = textureSample(t_input, s_input, v_tex_coords);
+}
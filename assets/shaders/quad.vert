@@ -13,15 +13,13 @@ layout(location=9) in vec4 a_color;
 layout(location=0) out vec4 v_color;
 layout(location=1) out vec3 v_world_position;
 layout(location=2) out vec3 v_normal;
+layout(location=3) out vec2 v_tex_coords;
 
-layout(set=0, binding=0)
-uniform Uniforms {
-    mat4 u_view_proj;
-};
-
+#include "include/uniforms.glsl"
 
 void main() {
     v_color = a_color;
+    v_tex_coords = a_tex_coords;
 
     mat4 model_matrix = mat4(
         model_matrix_0,
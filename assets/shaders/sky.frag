@@ -0,0 +1,42 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+#include "include/uniforms.glsl"
+#include "include/lights.glsl"
+
+const vec3 DAY_ZENITH = vec3(0.12, 0.3, 0.75);
+const vec3 DAY_HORIZON = vec3(0.6, 0.72, 0.9);
+const vec3 NIGHT_ZENITH = vec3(0.002, 0.004, 0.01);
+const vec3 NIGHT_HORIZON = vec3(0.01, 0.015, 0.03);
+const vec3 SUNSET_HORIZON = vec3(1.0, 0.45, 0.15);
+const vec3 GROUND = vec3(0.1, 0.09, 0.08);
+// Roughly the 0.53 degree diameter of the real sun.
+const float SUN_ANGULAR_RADIUS = 0.0046;
+const float SUN_DISK_INTENSITY = 50.0;
+
+void main() {
+    vec4 far_position = u_inverse_view_proj * vec4(v_tex_coords.x * 2.0 - 1.0, 1.0 - v_tex_coords.y * 2.0, 1.0, 1.0);
+    vec3 view_dir = normalize(far_position.xyz / far_position.w - u_view_position.xyz);
+    vec3 sun_dir = normalize(u_sun_direction.xyz);
+
+    float day = smoothstep(-0.1, 0.25, sun_dir.y);
+    // The horizon reddens towards the sun while it is low.
+    float sunset = (1.0 - smoothstep(0.0, 0.3, sun_dir.y)) * smoothstep(-0.15, 0.0, sun_dir.y);
+    float towards_sun = dot(view_dir, sun_dir) * 0.5 + 0.5;
+
+    vec3 zenith = mix(NIGHT_ZENITH, DAY_ZENITH, day);
+    vec3 horizon = mix(NIGHT_HORIZON, DAY_HORIZON, day);
+    horizon = mix(horizon, SUNSET_HORIZON, sunset * towards_sun * towards_sun);
+
+    vec3 color = mix(horizon, zenith, sqrt(max(view_dir.y, 0.0)));
+    color = mix(color, GROUND * day, 1.0 - smoothstep(-0.1, 0.0, view_dir.y));
+
+    float cos_angle = dot(view_dir, sun_dir);
+    float disk = smoothstep(cos(SUN_ANGULAR_RADIUS * 1.5), cos(SUN_ANGULAR_RADIUS), cos_angle);
+    float glow = pow(max(cos_angle, 0.0), 256.0) * 0.5 + pow(max(cos_angle, 0.0), 8.0) * 0.1;
+    color += u_sun_color.rgb * (disk * SUN_DISK_INTENSITY + glow) * smoothstep(-0.02, 0.0, view_dir.y);
+
+    f_color = vec4(color, 1.0);
+}
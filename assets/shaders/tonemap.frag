@@ -0,0 +1,48 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_hdr;
+layout(set=0, binding=1) uniform sampler s_hdr;
+
+layout(set=0, binding=2)
+uniform ToneMapping {
+    float u_exposure;
+    float u_gamma;
+    uint u_operator;
+    uint u_encode_srgb;
+};
+
+const uint OPERATOR_REINHARD = 0;
+const uint OPERATOR_ACES = 1;
+
+vec3 reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+vec3 aces(vec3 color) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+void main() {
+    vec3 hdr = texture(sampler2D(t_hdr, s_hdr), v_tex_coords).rgb * u_exposure;
+    vec3 color = u_operator == OPERATOR_ACES ? aces(hdr) : reinhard(hdr);
+    color = pow(color, vec3(1.0 / u_gamma));
+    if (u_encode_srgb != 0) {
+        color = linear_to_srgb(color);
+    }
+    f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coords;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_input;
+layout(set=0, binding=1) uniform sampler s_input;
+layout(set=0, binding=2) uniform texture2D t_aux;
+layout(set=0, binding=3) uniform sampler s_aux;
+
+layout(set=0, binding=4)
+uniform PostParams {
+    vec4 u_params;
+};
+
+// u_params.x: intensity, u_params.y: radius, u_params.z: smoothness
+
+void main() {
+    vec3 color = texture(sampler2D(t_input, s_input), v_tex_coords).rgb;
+    // Scaled so the corners are at distance 1.
+    float edge_distance = length(v_tex_coords - 0.5) * sqrt(2.0);
+    float darkening = smoothstep(u_params.y, u_params.y + u_params.z, edge_distance);
+    f_color = vec4(color * (1.0 - darkening * u_params.x), 1.0);
+}
@@ -0,0 +1,197 @@
+use crate::{
+    assets::AssetOwner,
+    file_watcher::FileWatcher,
+    render_graph::{PassDescriptor, TargetDescriptor, TargetId},
+    PipelineHandler, State,
+};
+use std::{collections::HashSet, path::PathBuf, time::Instant};
+use winit::{
+    dpi::LogicalSize,
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+type PipelineFactory = Box<dyn FnOnce(&State) -> Box<dyn PipelineHandler>>;
+
+pub struct App {
+    title: String,
+    size: LogicalSize<u32>,
+    render_targets: Vec<(TargetId, TargetDescriptor)>,
+    render_passes: Vec<PassDescriptor>,
+    pipeline_factories: Vec<PipelineFactory>,
+}
+
+fn resize(
+    state: &mut State,
+    pipelines: &mut [Box<dyn PipelineHandler>],
+    size: winit::dpi::PhysicalSize<u32>,
+) {
+    state.resize(size);
+    for (index, pipeline) in pipelines.iter_mut().enumerate() {
+        state.begin_error_report(AssetOwner::Pipeline(index), false);
+        pipeline.resized(state);
+        state.end_error_report();
+    }
+}
+
+fn register_assets(state: &mut State, pipelines: &[Box<dyn PipelineHandler>]) {
+    for (index, pipeline) in pipelines.iter().enumerate() {
+        state.register_assets(AssetOwner::Pipeline(index), pipeline.dependencies());
+    }
+}
+
+fn files_changed(
+    state: &mut State,
+    pipelines: &mut [Box<dyn PipelineHandler>],
+    changed: &HashSet<PathBuf>,
+) {
+    // Dependencies are gathered again first, as they move with edited includes and with the
+    // scene, e.g. post effects swapping their shaders and LUTs.
+    register_assets(state, pipelines);
+    for (owner, files) in state.assets().route(changed) {
+        match owner {
+            AssetOwner::Scene => state.files_changed(&files),
+            AssetOwner::Pipeline(index) => {
+                // Errors of the reload replace the ones shown for the pipeline.
+                state.begin_error_report(owner, true);
+                pipelines[index].files_changed(state, &files);
+                state.end_error_report();
+            }
+        }
+    }
+}
+
+impl App {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_owned(),
+            size: LogicalSize::new(1024, 720),
+            render_targets: Vec::new(),
+            render_passes: Vec::new(),
+            pipeline_factories: Vec::new(),
+        }
+    }
+
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = LogicalSize::new(width, height);
+        self
+    }
+
+    /// Adds a render target to the render graph before any pipeline is created.
+    pub fn with_render_target(mut self, id: TargetId, desc: TargetDescriptor) -> Self {
+        self.render_targets.push((id, desc));
+        self
+    }
+
+    /// Adds a pass to the render graph before any pipeline is created.
+    pub fn with_render_pass(mut self, desc: PassDescriptor) -> Self {
+        self.render_passes.push(desc);
+        self
+    }
+
+    /// Registers a pipeline, created once the device is available. Pipelines render in the
+    /// order they are added.
+    pub fn with_pipeline<P, F>(mut self, factory: F) -> Self
+    where
+        P: PipelineHandler + 'static,
+        F: FnOnce(&State) -> P + 'static,
+    {
+        self.pipeline_factories
+            .push(Box::new(move |state| Box::new(factory(state))));
+        self
+    }
+
+    pub fn run(self) -> ! {
+        let startup_time = Instant::now();
+        let mut last_update_time = None;
+        let file_watcher = FileWatcher::default();
+
+        let event_loop = EventLoop::new();
+        let window = WindowBuilder::new()
+            .with_title(&self.title)
+            .with_inner_size(self.size)
+            .build(&event_loop)
+            .unwrap();
+
+        use futures::executor::block_on;
+
+        let mut state = block_on(State::new(&window));
+        for (id, desc) in self.render_targets {
+            state.add_render_target(id, desc);
+        }
+        for desc in self.render_passes {
+            state.add_render_pass(desc);
+        }
+        let mut pipelines: Vec<Box<dyn PipelineHandler>> = self
+            .pipeline_factories
+            .into_iter()
+            .enumerate()
+            .map(|(index, factory)| {
+                state.begin_error_report(AssetOwner::Pipeline(index), true);
+                let pipeline = factory(&state);
+                state.end_error_report();
+                pipeline
+            })
+            .collect();
+        register_assets(&mut state, &pipelines);
+
+        event_loop.run(move |event, _, control_flow| {
+            *control_flow = ControlFlow::Poll;
+            match event {
+                //Event::DeviceEvent { ref event, .. } => {}
+                Event::WindowEvent {
+                    ref event,
+                    window_id,
+                } if window_id == window.id() => match event {
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        state.keyboard_input_event(input);
+                        if let KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        } = input
+                        {
+                            *control_flow = ControlFlow::Exit
+                        }
+                    }
+                    WindowEvent::Resized(size) => resize(&mut state, &mut pipelines, *size),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        resize(&mut state, &mut pipelines, **new_inner_size)
+                    }
+                    _ => {}
+                },
+                Event::RedrawRequested(_) => {
+                    let now = Instant::now();
+                    let delta = now - last_update_time.unwrap_or(now);
+                    last_update_time = Some(now);
+                    state.update(delta, now - startup_time);
+                    for (index, pipeline) in pipelines.iter_mut().enumerate() {
+                        state.begin_error_report(AssetOwner::Pipeline(index), false);
+                        pipeline.update(&mut state);
+                        state.end_error_report();
+                    }
+                    match state.render(&mut pipelines) {
+                        Ok(_) => {}
+                        Err(wgpu::SwapChainError::Lost) => {
+                            let size = state.size();
+                            resize(&mut state, &mut pipelines, size)
+                        }
+                        Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                        Err(wgpu::SwapChainError::Outdated) => {}
+                        Err(wgpu::SwapChainError::Timeout) => {}
+                    }
+
+                    if let Some(changed) = file_watcher.collect_modified() {
+                        files_changed(&mut state, &mut pipelines, &changed);
+                    }
+                }
+                Event::MainEventsCleared => {
+                    window.request_redraw();
+                }
+                _ => {}
+            }
+        });
+    }
+}
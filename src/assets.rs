@@ -0,0 +1,92 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::pipeline::shader_includes;
+
+/// Files something was built from, relative to the crate root like the paths it loads.
+#[derive(Clone, Debug, Default)]
+pub struct Dependencies {
+    paths: HashSet<PathBuf>,
+}
+
+impl Dependencies {
+    pub fn with_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.paths.insert(path.as_ref().to_owned());
+        self
+    }
+
+    /// Adds the shader and every file it includes.
+    pub fn with_shader<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.paths.extend(shader_includes(path.as_ref()));
+        self.with_file(path)
+    }
+
+    pub fn with_dependencies(mut self, dependencies: Dependencies) -> Self {
+        self.paths.extend(dependencies.paths);
+        self
+    }
+
+    /// Whether `path`, as reported by the file watcher, is one of the dependencies. Watcher paths
+    /// are absolute, so they are matched by suffix.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths
+            .iter()
+            .any(|dependency| path.ends_with(dependency))
+    }
+
+    pub fn any_changed(&self, changed: &HashSet<PathBuf>) -> bool {
+        changed.iter().any(|path| self.contains(path))
+    }
+}
+
+/// Something that loads files and is told when they change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AssetOwner {
+    /// The scene settings of `State`.
+    Scene,
+    /// A pipeline, by the order it was added to the `App`.
+    Pipeline(usize),
+}
+
+/// The files every owner depends on, so file changes only reach the owners built from them.
+#[derive(Debug, Default)]
+pub struct AssetRegistry {
+    owners: BTreeMap<AssetOwner, Dependencies>,
+}
+
+impl AssetRegistry {
+    /// Replaces the dependencies of `owner`.
+    pub fn register(&mut self, owner: AssetOwner, dependencies: Dependencies) {
+        self.owners.insert(owner, dependencies);
+    }
+
+    pub fn unregister(&mut self, owner: AssetOwner) {
+        self.owners.remove(&owner);
+    }
+
+    pub fn dependencies(&self, owner: AssetOwner) -> Option<&Dependencies> {
+        self.owners.get(&owner)
+    }
+
+    /// The changed files of each owner depending on any of `changed`, scene first and then
+    /// pipelines in order.
+    pub fn route(&self, changed: &HashSet<PathBuf>) -> Vec<(AssetOwner, HashSet<PathBuf>)> {
+        self.owners
+            .iter()
+            .filter_map(|(&owner, dependencies)| {
+                let files: HashSet<PathBuf> = changed
+                    .iter()
+                    .filter(|path| dependencies.contains(path))
+                    .cloned()
+                    .collect();
+                if files.is_empty() {
+                    None
+                } else {
+                    Some((owner, files))
+                }
+            })
+            .collect()
+    }
+}
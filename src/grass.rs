@@ -1,10 +1,18 @@
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::HashSet,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use rand::{distributions::Uniform, SeedableRng};
 use ultraviolet::{Mat4, Vec2, Vec3};
 use wgpu::util::DeviceExt as _;
 
-use crate::{pipeline::create_default_pipeline, texture::Texture, PipelineHandler, State};
+use crate::{
+    material::{self, AlphaMode, Material},
+    pipeline::{BlendMode, PermutationCache, PipelineBuilder, ShaderDefines},
+    Dependencies, PassId, PipelineHandler, State,
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -36,32 +44,97 @@ impl Vertex {
     }
 }
 
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: Vec3::new(0.0, 1.0, 0.0),
-        tex_coords: Vec2::new(0.5, 1.0),
-        normal: Vec3::new(0.0, 0.0, 1.0),
-    },
-    Vertex {
-        position: Vec3::new(-0.5, 0.0, 0.0),
-        tex_coords: Vec2::new(0.0, 0.0),
-        normal: Vec3::new(0.0, 0.0, 1.0),
-    },
-    Vertex {
-        position: Vec3::new(0.5, 0.0, 0.0),
-        tex_coords: Vec2::new(1.0, 0.0),
-        normal: Vec3::new(0.0, 0.0, 1.0),
-    },
-];
+/// Blade geometry. Meshes are laid out with x across the blade in [-0.5, 0.5] and y from the
+/// root (0) to the tip (1), which the wind in `grass.vert` relies on.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BladeMesh {
+    /// A single triangle.
+    Triangle = 0,
+    /// Three segments narrowing to the tip, bends more smoothly in the wind.
+    Tapered = 1,
+    /// A full quad, for texture cards such as flowers or clover.
+    Card = 2,
+}
+
+impl BladeMesh {
+    const ALL: [BladeMesh; 3] = [BladeMesh::Triangle, BladeMesh::Tapered, BladeMesh::Card];
+
+    /// The mesh stored in `Instance::mesh`, unknown values fall back to `Triangle`.
+    pub fn from_index(index: u32) -> Self {
+        Self::ALL
+            .get(index as usize)
+            .copied()
+            .unwrap_or(BladeMesh::Triangle)
+    }
+
+    fn triangles(self) -> &'static [[f32; 2]] {
+        match self {
+            BladeMesh::Triangle => &[[0.0, 1.0], [-0.5, 0.0], [0.5, 0.0]],
+            BladeMesh::Tapered => &[
+                [-0.4, 0.4],
+                [-0.5, 0.0],
+                [0.5, 0.0],
+                [-0.4, 0.4],
+                [0.5, 0.0],
+                [0.4, 0.4],
+                [-0.25, 0.75],
+                [-0.4, 0.4],
+                [0.4, 0.4],
+                [-0.25, 0.75],
+                [0.4, 0.4],
+                [0.25, 0.75],
+                [0.0, 1.0],
+                [-0.25, 0.75],
+                [0.25, 0.75],
+            ],
+            BladeMesh::Card => &[
+                [-0.5, 1.0],
+                [-0.5, 0.0],
+                [0.5, 0.0],
+                [-0.5, 1.0],
+                [0.5, 0.0],
+                [0.5, 1.0],
+            ],
+        }
+    }
+}
+
+/// Vertices of every `BladeMesh` back to back, with the vertex range of each mesh.
+fn mesh_vertices() -> (Vec<Vertex>, Vec<Range<u32>>) {
+    let mut vertices = Vec::new();
+    let mut ranges = Vec::new();
+    for mesh in BladeMesh::ALL.iter() {
+        let start = vertices.len() as u32;
+        vertices.extend(mesh.triangles().iter().map(|&[x, y]| Vertex {
+            position: Vec3::new(x, y, 0.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            tex_coords: Vec2::new(x + 0.5, y),
+        }));
+        ranges.push(start..vertices.len() as u32);
+    }
+    (vertices, ranges)
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Instance {
-    mat: Mat4,
+pub struct Instance {
+    pub mat: Mat4,
+    pub color_bottom: Vec3,
+    pub color_top: Vec3,
+    /// Fraction of the wind sway the blade resists, 0 sways fully and 1 doesn't move.
+    pub stiffness: f32,
+    /// Albedo texture array layer of the blade.
+    pub layer: u32,
+    /// `BladeMesh` of the blade as `u32`. Only used to group the draws, the shaders don't read
+    /// it.
+    pub mesh: u32,
+    /// Multiplied with the blade color after the season is applied, white leaves it unchanged.
+    pub tint: Vec3,
 }
 
 impl Instance {
-    pub fn attributes() -> [wgpu::VertexAttribute; 4] {
+    pub fn attributes() -> [wgpu::VertexAttribute; 9] {
         [
             wgpu::VertexAttribute {
                 offset: 0,
@@ -83,112 +156,566 @@ impl Instance {
                 shader_location: 8,
                 format: wgpu::VertexFormat::Float4,
             },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 23]>() as _,
+                shader_location: 9,
+                format: wgpu::VertexFormat::Uint,
+            },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 16]>() as _,
+                shader_location: 10,
+                format: wgpu::VertexFormat::Float3,
+            },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 19]>() as _,
+                shader_location: 11,
+                format: wgpu::VertexFormat::Float3,
+            },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 22]>() as _,
+                shader_location: 12,
+                format: wgpu::VertexFormat::Float,
+            },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 25]>() as _,
+                shader_location: 13,
+                format: wgpu::VertexFormat::Float3,
+            },
         ]
     }
 }
 
-fn create_bundle(state: &State) -> Option<wgpu::RenderBundle> {
-    let device = &state.device;
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(&VERTICES),
-        usage: wgpu::BufferUsage::VERTEX,
-    });
+/// Look and placement of one kind of grass.
+#[derive(Clone, Debug)]
+pub struct GrassSpecies {
+    /// Blade color at the root, blended to `color_top` at the tip.
+    pub color_bottom: Vec3,
+    pub color_top: Vec3,
+    pub width: (f32, f32),
+    pub height: (f32, f32),
+    /// Fraction of the wind sway the blades resist, 0 sways fully and 1 doesn't move.
+    pub stiffness: f32,
+    pub mesh: BladeMesh,
+    /// Albedo texture array layer.
+    pub layer: u32,
+    /// Relative share of the blades.
+    pub weight: f32,
+    /// Grayscale image stretched over the patch that scales `weight`, x along +x and y along
+    /// +z. Places where every species has zero density stay bare.
+    pub density_map: Option<PathBuf>,
+}
+
+impl Default for GrassSpecies {
+    fn default() -> Self {
+        Self {
+            color_bottom: Vec3::new(20.0 / 255.0, 40.0 / 255.0, 0.0),
+            color_top: Vec3::new(40.0 / 255.0, 80.0 / 255.0, 0.0),
+            width: (0.02, 0.04),
+            height: (0.04, 0.08),
+            stiffness: 0.0,
+            mesh: BladeMesh::Triangle,
+            layer: 0,
+            weight: 1.0,
+            density_map: None,
+        }
+    }
+}
+
+fn load_density_map(path: &Path) -> Option<image::GrayImage> {
+    match image::open(path) {
+        Ok(image) => Some(image.into_luma8()),
+        Err(err) => {
+            log::error!("Failed to load density map {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+fn load_color_map(path: &Path) -> Option<image::RgbImage> {
+    match image::open(path) {
+        Ok(image) => Some(image.into_rgb8()),
+        Err(err) => {
+            log::error!("Failed to load color map {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Nearest texel to `uv` in [0, 1] of an image of the given size.
+fn nearest_texel((width, height): (u32, u32), uv: Vec2) -> (u32, u32) {
+    let x = ((uv.x * width as f32) as u32).min(width - 1);
+    let y = ((uv.y * height as f32) as u32).min(height - 1);
+    (x, y)
+}
+
+/// Nearest texel of `map` at `uv` in [0, 1], as a density in [0, 1].
+fn sample_density(map: &image::GrayImage, uv: Vec2) -> f32 {
+    let (x, y) = nearest_texel(map.dimensions(), uv);
+    map.get_pixel(x, y)[0] as f32 / 255.0
+}
+
+fn sample_color(map: &image::RgbImage, uv: Vec2) -> Vec3 {
+    let (x, y) = nearest_texel(map.dimensions(), uv);
+    let [r, g, b] = map.get_pixel(x, y).0;
+    Vec3::new(r as f32, g as f32, b as f32) / 255.0
+}
+
+fn hash(seed: u32, x: i32, y: i32) -> f32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841);
+    h = (h ^ (h >> 13)).wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    h as f32 / u32::MAX as f32
+}
+
+/// Smoothly interpolated value noise in [-1, 1] with one random value per unit cell.
+fn value_noise(seed: u32, p: Vec2) -> f32 {
+    let (x, y) = (p.x.floor(), p.y.floor());
+    let (cx, cy) = (x as i32, y as i32);
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(p.x - x), smooth(p.y - y));
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let bottom = lerp(hash(seed, cx, cy), hash(seed, cx + 1, cy), tx);
+    let top = lerp(hash(seed, cx, cy + 1), hash(seed, cx + 1, cy + 1), tx);
+    lerp(bottom, top, ty) * 2.0 - 1.0
+}
+
+/// Low-frequency color variation across the patch, baked into `Instance::tint`.
+#[derive(Clone, Debug)]
+pub struct GrassTint {
+    /// Noise cells across the whole patch.
+    pub frequency: f32,
+    /// Largest change in brightness, 0.1 is ±10%.
+    pub brightness: f32,
+    /// Largest shift toward yellow, or toward blue for negative noise.
+    pub hue_shift: f32,
+    /// Color image stretched over the patch like `GrassSpecies::density_map`, multiplied into
+    /// the tint. White leaves the blades unchanged.
+    pub color_map: Option<PathBuf>,
+}
+
+impl Default for GrassTint {
+    fn default() -> Self {
+        Self {
+            frequency: 4.0,
+            brightness: 0.15,
+            hue_shift: 0.1,
+            color_map: None,
+        }
+    }
+}
+
+impl GrassTint {
+    fn sample(&self, seed: u32, uv: Vec2, color_map: Option<&image::RgbImage>) -> Vec3 {
+        let p = uv * self.frequency;
+        let brightness = 1.0 + self.brightness * value_noise(seed, p);
+        let hue = self.hue_shift * value_noise(seed.wrapping_add(1), p);
+        let tint = Vec3::new(1.0 + hue, 1.0, 1.0 - hue) * brightness;
+        color_map.map_or(tint, |map| tint * sample_color(map, uv))
+    }
+}
+
+/// Scatters grass blades over a square patch centered at the origin, mixing `species` by their
+/// weights.
+#[derive(Clone, Debug)]
+pub struct GrassGenerator {
+    pub seed: u64,
+    pub count: usize,
+    pub half_extent: f32,
+    pub species: Vec<GrassSpecies>,
+    pub tint: GrassTint,
+}
+
+impl Default for GrassGenerator {
+    /// Green blades mixed with dry, tapered ones drawn from albedo layer 1.
+    fn default() -> Self {
+        let dry = GrassSpecies {
+            color_bottom: Vec3::new(60.0 / 255.0, 50.0 / 255.0, 10.0 / 255.0),
+            color_top: Vec3::new(150.0 / 255.0, 130.0 / 255.0, 50.0 / 255.0),
+            height: (0.05, 0.1),
+            stiffness: 0.4,
+            mesh: BladeMesh::Tapered,
+            layer: 1,
+            weight: 0.3,
+            ..Default::default()
+        };
+        Self {
+            seed: 0,
+            count: 20000,
+            half_extent: 1.0,
+            species: vec![GrassSpecies::default(), dry],
+            tint: GrassTint::default(),
+        }
+    }
+}
+
+impl GrassGenerator {
+    pub fn generate(&self) -> Vec<Instance> {
+        use rand::{distributions::Distribution as _, Rng as _};
+
+        let mut rng = rand_hc::Hc128Rng::seed_from_u64(self.seed);
+        let pos_range = Uniform::new(-self.half_extent, self.half_extent);
+        let density_maps: Vec<_> = self
+            .species
+            .iter()
+            .map(|species| species.density_map.as_deref().and_then(load_density_map))
+            .collect();
+        let color_map = self.tint.color_map.as_deref().and_then(load_color_map);
+        let noise_seed = self.seed as u32 ^ (self.seed >> 32) as u32;
+        let mut weights = vec![0.0; self.species.len()];
 
-    let mut instances = Vec::new();
+        (0..self.count)
+            .filter_map(|_| {
+                let pos = Vec3::new(pos_range.sample(&mut rng), 0.0, pos_range.sample(&mut rng));
+                let uv = Vec2::new(pos.x, pos.z) / (2.0 * self.half_extent) + Vec2::broadcast(0.5);
+                for ((weight, species), map) in weights
+                    .iter_mut()
+                    .zip(self.species.iter())
+                    .zip(density_maps.iter())
+                {
+                    let density = map.as_ref().map_or(1.0, |map| sample_density(map, uv));
+                    *weight = species.weight.max(0.0) * density;
+                }
+
+                let total: f32 = weights.iter().sum();
+                if total <= 0.0 {
+                    return None;
+                }
+                let mut pick = rng.gen::<f32>() * total;
+                let index = weights
+                    .iter()
+                    .position(|weight| {
+                        pick -= weight;
+                        pick < 0.0
+                    })
+                    .unwrap_or(weights.len() - 1);
+                let species = &self.species[index];
+
+                let lerp = |(min, max): (f32, f32), t: f32| min + (max - min) * t;
+                let width = lerp(species.width, rng.gen());
+                let height = lerp(species.height, rng.gen());
+                Some(Instance {
+                    mat: Mat4::from_translation(pos)
+                        * Mat4::from_nonuniform_scale(Vec3::new(width, height, width)),
+                    color_bottom: species.color_bottom,
+                    color_top: species.color_top,
+                    stiffness: species.stiffness,
+                    layer: species.layer,
+                    mesh: species.mesh as u32,
+                    tint: self.tint.sample(noise_seed, uv, color_map.as_ref()),
+                })
+            })
+            .collect()
+    }
+}
 
-    use rand::distributions::Distribution as _;
+const ALBEDO_PATHS: [&str; 2] = [
+    "assets/textures/grass_blade.png",
+    "assets/textures/grass_blade_dry.png",
+];
+const VERTEX_SHADER: &str = "assets/shaders/grass.vert";
+const FRAGMENT_SHADER: &str = "assets/shaders/grass.frag";
+const SHADOW_FRAGMENT_SHADER: &str = "assets/shaders/grass_shadow.frag";
+
+fn shader_dependencies() -> Dependencies {
+    Dependencies::default()
+        .with_shader(VERTEX_SHADER)
+        .with_shader(FRAGMENT_SHADER)
+        .with_shader(SHADOW_FRAGMENT_SHADER)
+}
+
+/// Blades sharing a texture array and an alpha mode, drawn in one instanced draw.
+#[derive(Clone)]
+pub struct GrassLayer {
+    pub instances: Vec<Instance>,
+    /// One image per albedo array layer, selected by `Instance::layer`.
+    pub albedo: Vec<PathBuf>,
+    /// `AlphaMode::Blended` layers are drawn with a single mesh so all their blades sort back to
+    /// front together. Blades with another mesh than the first one are drawn with its mesh.
+    pub alpha_mode: AlphaMode,
+}
+
+impl Default for GrassLayer {
+    /// No blades yet, textured with the green and dry blade albedos.
+    fn default() -> Self {
+        Self {
+            instances: Vec::new(),
+            albedo: ALBEDO_PATHS.iter().map(PathBuf::from).collect(),
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+}
 
-    let mut rng = rand_hc::Hc128Rng::seed_from_u64(0);
-    let pos_range = Uniform::new(-1.0f32, 1.0);
-    let width_range = Uniform::new(0.02f32, 0.04);
-    let height_range = Uniform::new(0.04f32, 0.08);
+/// Sorts instances by mesh and returns the instance range drawn with each mesh.
+fn group_by_mesh(instances: &mut [Instance]) -> Vec<(BladeMesh, Range<u32>)> {
+    instances.sort_by_key(|instance| BladeMesh::from_index(instance.mesh));
+    let mut groups: Vec<(BladeMesh, Range<u32>)> = Vec::new();
+    for (i, instance) in instances.iter().enumerate() {
+        let mesh = BladeMesh::from_index(instance.mesh);
+        match groups.last_mut() {
+            Some((last, range)) if *last == mesh => range.end = i as u32 + 1,
+            _ => groups.push((mesh, i as u32..i as u32 + 1)),
+        }
+    }
+    groups
+}
 
-    for _ in 0..20000 {
-        let pos = Vec3::new(pos_range.sample(&mut rng), 0.0, pos_range.sample(&mut rng));
-        let width = width_range.sample(&mut rng);
-        let height = height_range.sample(&mut rng);
-        instances.push(Instance {
-            mat: Mat4::from_translation(pos)
-                * Mat4::from_nonuniform_scale(Vec3::new(width, height, width)),
-        });
+/// Gives every blade the mesh of the first one, as a blended layer is sorted as a whole and
+/// drawn in one draw.
+fn use_single_mesh(instances: &mut [Instance]) {
+    let mesh = match instances.first() {
+        Some(first) => first.mesh,
+        None => return,
+    };
+    if instances.iter().any(|instance| instance.mesh != mesh) {
+        log::warn!(
+            "Blended grass layers use one mesh, drawing every blade as {:?}.",
+            BladeMesh::from_index(mesh)
+        );
+        for instance in instances {
+            instance.mesh = mesh;
+        }
     }
+}
 
-    let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Instance Buffer"),
-        contents: bytemuck::cast_slice(&instances),
+/// Blended layers share one mesh, so this is the order of their single draw.
+fn sort_back_to_front(instances: &mut [Instance], view_position: Vec3) {
+    let distance_sq = |instance: &Instance| (instance.mat.cols[3].xyz() - view_position).mag_sq();
+    instances.sort_unstable_by(|a, b| {
+        distance_sq(b)
+            .partial_cmp(&distance_sq(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+fn create_bundle(
+    state: &State,
+    instance_buffer: &wgpu::Buffer,
+    meshes: &[(BladeMesh, Range<u32>)],
+    material: &Material,
+    defines: &ShaderDefines,
+    pass: PassId,
+) -> Option<wgpu::RenderBundle> {
+    let device = &state.device;
+    let (vertices, vertex_ranges) = mesh_vertices();
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Vertex Buffer"),
+        contents: bytemuck::cast_slice(&vertices),
         usage: wgpu::BufferUsage::VERTEX,
     });
 
-    let pipeline = create_default_pipeline(
-        &state.device,
-        &state.sc_desc,
-        &[&state.uniform_bind_group_layout],
-        &[
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                step_mode: wgpu::InputStepMode::Vertex,
-                attributes: &Vertex::attributes(),
-            },
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
-                step_mode: wgpu::InputStepMode::Instance,
-                attributes: &Instance::attributes(),
-            },
-        ],
-        "assets/shaders/grass.vert",
-        "assets/shaders/blinn_phong.frag",
-    )?;
+    let formats = state.pass_formats(pass)?;
+    let bind_group_layouts = [
+        state.pass_bind_group_layout(pass),
+        state.material_bind_group_layout(),
+    ];
+    let vertex_buffers_layout = [
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &Vertex::attributes(),
+        },
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &Instance::attributes(),
+        },
+    ];
+    // Cut out texels don't cast shadows either.
+    let masked_shadow = material.alpha_mode() != AlphaMode::Opaque;
+    let mut uniform_blocks = state.pass_uniform_blocks(pass);
+    if pass != PassId::SHADOW || masked_shadow {
+        uniform_blocks.push(material::uniform_block());
+    }
+    let builder = PipelineBuilder::new(VERTEX_SHADER)
+        .with_defines(defines)
+        .with_formats(&formats)
+        .with_vertex_buffers(&vertex_buffers_layout)
+        .with_uniform_blocks(&uniform_blocks);
+    let builder = if pass == PassId::SHADOW {
+        let builder = builder.for_shadow_casting();
+        if masked_shadow {
+            builder
+                .with_fragment_shader(SHADOW_FRAGMENT_SHADER)
+                .with_bind_group_layouts(&bind_group_layouts)
+        } else {
+            builder.with_bind_group_layouts(&bind_group_layouts[..1])
+        }
+    } else {
+        let builder = builder
+            .with_fragment_shader(FRAGMENT_SHADER)
+            .with_bind_group_layouts(&bind_group_layouts);
+        match material.alpha_mode() {
+            AlphaMode::Opaque => builder,
+            // Opaque, with the fragment alpha turned into sample coverage when multisampled.
+            AlphaMode::Cutout => builder.with_alpha_to_coverage(formats.sample_count > 1),
+            // Tested against depth without writing it, so it is drawn after opaque geometry.
+            AlphaMode::Blended => builder
+                .with_blend_mode(BlendMode::Alpha)
+                .with_depth_write(false),
+        }
+    };
+    let pipeline = state.report_errors(builder.build(&state.device))?;
 
-    let mut encoder =
-        state
-            .device
-            .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
-                label: None,
-                color_formats: &[state.sc_desc.format],
-                depth_stencil_format: Some(Texture::DEPTH_FORMAT),
-                sample_count: Texture::MSAA_SAMPLES,
-            });
+    let mut encoder = formats.create_bundle_encoder(&state.device, None);
 
     encoder.set_pipeline(&pipeline);
     encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
     encoder.set_vertex_buffer(1, instance_buffer.slice(..));
-    encoder.set_bind_group(0, &state.uniform_bind_group, &[]);
+    encoder.set_bind_group(0, state.pass_bind_group(pass), &[]);
+    if pass != PassId::SHADOW || masked_shadow {
+        encoder.set_bind_group(1, material.bind_group(), &[]);
+    }
 
-    encoder.draw(0..VERTICES.len() as _, 0..instances.len() as _);
+    for (mesh, instances) in meshes {
+        encoder.draw(vertex_ranges[*mesh as usize].clone(), instances.clone());
+    }
 
     Some(encoder.finish(&wgpu::RenderBundleDescriptor {
-        label: Some("grass"),
+        label: Some(if pass == PassId::SHADOW {
+            "grass_shadow"
+        } else {
+            "grass"
+        }),
     }))
 }
 
+/// Draws one `GrassLayer`, with a draw per `BladeMesh` of opaque and cutout layers. Register a pipeline per layer to
+/// combine alpha modes or texture arrays.
 pub struct GrassPipeline {
-    render_bundle: Option<wgpu::RenderBundle>,
+    instances: Vec<Instance>,
+    instance_buffer: wgpu::Buffer,
+    /// Instance range of each mesh, instances are kept grouped by mesh.
+    meshes: Vec<(BladeMesh, Range<u32>)>,
+    material: Material,
+    /// View position the blended instances were last sorted for.
+    sorted_from: Option<Vec3>,
+    /// Bundles of every define set used since the last reload, following
+    /// `State::shader_defines`.
+    bundles: PermutationCache,
 }
 
 impl GrassPipeline {
     pub fn create(state: &State) -> Self {
-        Self {
-            render_bundle: create_bundle(state),
+        Self::with_generator(state, &GrassGenerator::default())
+    }
+
+    pub fn with_generator(state: &State, generator: &GrassGenerator) -> Self {
+        Self::with_instances(state, generator.generate())
+    }
+
+    pub fn with_instances(state: &State, instances: Vec<Instance>) -> Self {
+        Self::with_layer(
+            state,
+            GrassLayer {
+                instances,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn with_layer(state: &State, mut layer: GrassLayer) -> Self {
+        if layer.alpha_mode == AlphaMode::Blended {
+            use_single_mesh(&mut layer.instances);
+        }
+        let meshes = group_by_mesh(&mut layer.instances);
+        let instance_buffer = state
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(&layer.instances),
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            });
+        let draw_pass = Self::draw_pass(layer.alpha_mode);
+        let sample_count = state
+            .pass_formats(draw_pass)
+            .map_or(1, |formats| formats.sample_count);
+        let material = Material::load(state, &layer.albedo, layer.alpha_mode, sample_count);
+        let mut pipeline = Self {
+            instances: layer.instances,
+            instance_buffer,
+            meshes,
+            material,
+            sorted_from: None,
+            bundles: PermutationCache::default(),
+        };
+        pipeline.select_defines(state);
+        pipeline
+    }
+
+    fn draw_pass(alpha_mode: AlphaMode) -> PassId {
+        if alpha_mode == AlphaMode::Blended {
+            PassId::TRANSPARENT
+        } else {
+            PassId::MAIN
         }
     }
+
+    /// Switches to the bundles for the defines of `state`, building them the first time.
+    fn select_defines(&mut self, state: &State) {
+        let (instance_buffer, meshes, material) =
+            (&self.instance_buffer, &self.meshes, &self.material);
+        let draw_pass = Self::draw_pass(material.alpha_mode());
+        self.bundles
+            .select(state.shader_defines(), draw_pass, |defines, pass| {
+                create_bundle(state, instance_buffer, meshes, material, defines, pass)
+            });
+    }
+
+    fn reload(&mut self, state: &State, shadow: bool) {
+        let (instance_buffer, meshes, material) =
+            (&self.instance_buffer, &self.meshes, &self.material);
+        let draw_pass = Self::draw_pass(material.alpha_mode());
+        self.bundles
+            .reload("Grass", draw_pass, shadow, |defines, pass| {
+                create_bundle(state, instance_buffer, meshes, material, defines, pass)
+            });
+    }
 }
 
 impl PipelineHandler for GrassPipeline {
+    fn update(&mut self, state: &mut State) {
+        if self.bundles.defines() != state.shader_defines() {
+            self.select_defines(state);
+        }
+        if self.material.alpha_mode() != AlphaMode::Blended {
+            return;
+        }
+        let view_position = state.view_position();
+        if self.sorted_from == Some(view_position) {
+            return;
+        }
+        // The bundle keeps drawing from the same buffer, only its contents are reordered.
+        sort_back_to_front(&mut self.instances, view_position);
+        state.queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        self.sorted_from = Some(view_position);
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        shader_dependencies().with_dependencies(self.material.dependencies())
+    }
+
     fn files_changed(&mut self, state: &mut State, changed: &HashSet<PathBuf>) {
-        if changed.iter().any(|path| {
-            path.ends_with("assets/shaders/grass.vert")
-                || path.ends_with("assets/shaders/blinn_phong.frag")
-        }) {
-            let bundle = create_bundle(state);
-            if bundle.is_some() {
-                self.render_bundle = bundle;
-                log::info!("Grass bundle reloaded.");
-            }
+        let shaders_changed = shader_dependencies().any_changed(changed);
+        let material_changed = self.material.files_changed(state, changed);
+        if shaders_changed || material_changed {
+            let masked_shadow = self.material.alpha_mode() != AlphaMode::Opaque;
+            self.reload(state, shaders_changed || masked_shadow);
+        }
+    }
+
+    fn passes(&self) -> &[PassId] {
+        if self.material.alpha_mode() == AlphaMode::Blended {
+            &[PassId::SHADOW, PassId::TRANSPARENT]
+        } else {
+            &[PassId::SHADOW, PassId::MAIN]
         }
     }
 
-    fn render_bundle(&mut self, _state: &State) -> Option<&wgpu::RenderBundle> {
-        return self.render_bundle.as_ref();
+    fn render_bundle(&mut self, _state: &State, pass: PassId) -> Option<&wgpu::RenderBundle> {
+        self.bundles.bundle(pass)
     }
 }
@@ -37,17 +37,17 @@ impl<T: Copy + Eq + std::hash::Hash> Input<T> {
 
 
     pub fn pressed(&self, value: T) -> bool {
-        return self.pressed.contains(&value);
+        self.pressed.contains(&value)
     }
 
     #[allow(dead_code)]
     pub fn just_pressed(&self, value: T) -> bool {
-        return self.just_pressed.contains(&value);
+        self.just_pressed.contains(&value)
     }
 
     #[allow(dead_code)]
     pub fn just_released(&self, value: T) -> bool {
-        return self.just_pressed.contains(&value);
+        self.just_pressed.contains(&value)
     }
 
 }
\ No newline at end of file
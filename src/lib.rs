@@ -0,0 +1,54 @@
+mod app;
+pub mod assets;
+pub mod file_watcher;
+pub mod grass;
+pub mod input;
+pub mod lighting;
+pub mod material;
+mod overlay;
+pub mod perspective_camera;
+pub mod pipeline;
+pub mod post;
+pub mod quad;
+pub mod reflect;
+pub mod render_graph;
+pub mod scene;
+pub mod shader_cache;
+pub mod shadow;
+pub mod sky;
+mod state;
+pub mod texture;
+pub mod tonemap;
+
+use std::{collections::HashSet, path::PathBuf};
+
+pub use app::App;
+pub use assets::Dependencies;
+pub use grass::{BladeMesh, GrassGenerator, GrassLayer, GrassPipeline, GrassSpecies, GrassTint};
+pub use material::AlphaMode;
+pub use post::PostProcessPipeline;
+pub use quad::QuadPipeline;
+pub use render_graph::{PassId, TargetId};
+pub use sky::SkyPipeline;
+pub use state::State;
+pub use tonemap::TonemapPipeline;
+
+/// A renderer plugin. Every frame the bundles it returns are executed into the render graph
+/// passes it contributes to.
+pub trait PipelineHandler {
+    fn passes(&self) -> &[PassId] {
+        &[PassId::MAIN]
+    }
+    fn render_bundle(&mut self, state: &State, pass: PassId) -> Option<&wgpu::RenderBundle>;
+    /// Files the pipeline is built from. It is asked again whenever files change, so the set
+    /// can follow what the pipeline currently uses.
+    fn dependencies(&self) -> Dependencies {
+        Dependencies::default()
+    }
+    /// Called with the changed files among `dependencies`, never with an empty set.
+    fn files_changed(&mut self, state: &mut State, changed: &HashSet<PathBuf>);
+    /// Called every frame before rendering.
+    fn update(&mut self, _state: &mut State) {}
+    /// Called after the swap chain sized render targets were recreated.
+    fn resized(&mut self, _state: &State) {}
+}
@@ -0,0 +1,129 @@
+use serde::Deserialize;
+use ultraviolet::{Vec3, Vec4};
+
+use crate::reflect::member_offset;
+
+/// Size of the point light array in the lights uniform buffer. Extra lights are ignored.
+pub const MAX_POINT_LIGHTS: usize = 8;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct DirectionalLight {
+    /// Direction towards the light.
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely.
+    pub range: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Lights {
+    pub ambient_color: Vec3,
+    pub ambient_intensity: f32,
+    pub sun: DirectionalLight,
+    #[serde(default)]
+    pub point_lights: Vec<PointLight>,
+}
+
+impl Default for Lights {
+    fn default() -> Self {
+        Self {
+            ambient_color: Vec3::one(),
+            ambient_intensity: 0.1,
+            sun: DirectionalLight {
+                direction: Vec3::new(0.0, 1.0, 1.0).normalized(),
+                color: Vec3::one(),
+                intensity: 1.0,
+            },
+            point_lights: Vec::new(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightUniform {
+    position_range: Vec4,
+    color: Vec4,
+}
+
+// Matches the std140 `Lights` block in assets/shaders/include/lights.glsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LightsUniform {
+    sun_direction: Vec4,
+    sun_color: Vec4,
+    ambient_color: Vec4,
+    point_lights: [PointLightUniform; MAX_POINT_LIGHTS],
+    point_light_count: u32,
+    _padding: [u32; 3],
+}
+
+impl LightsUniform {
+    /// Offsets of the `Lights` block members.
+    pub(crate) fn members() -> Vec<(&'static str, u32)> {
+        let lights: Self = bytemuck::Zeroable::zeroed();
+        vec![
+            (
+                "u_sun_direction",
+                member_offset(&lights, &lights.sun_direction),
+            ),
+            ("u_sun_color", member_offset(&lights, &lights.sun_color)),
+            (
+                "u_ambient_color",
+                member_offset(&lights, &lights.ambient_color),
+            ),
+            (
+                "u_point_lights",
+                member_offset(&lights, &lights.point_lights),
+            ),
+            (
+                "u_point_light_count",
+                member_offset(&lights, &lights.point_light_count),
+            ),
+        ]
+    }
+}
+
+impl Lights {
+    pub(crate) fn to_uniform(&self) -> LightsUniform {
+        let mut point_lights = [PointLightUniform {
+            position_range: Vec4::zero(),
+            color: Vec4::zero(),
+        }; MAX_POINT_LIGHTS];
+        for (uniform, light) in point_lights.iter_mut().zip(self.point_lights.iter()) {
+            *uniform = PointLightUniform {
+                position_range: Vec4::new(
+                    light.position.x,
+                    light.position.y,
+                    light.position.z,
+                    light.range,
+                ),
+                color: (light.color * light.intensity).into(),
+            };
+        }
+        if self.point_lights.len() > MAX_POINT_LIGHTS {
+            log::warn!(
+                "Only {} of {} point lights are used.",
+                MAX_POINT_LIGHTS,
+                self.point_lights.len()
+            );
+        }
+
+        LightsUniform {
+            sun_direction: self.sun.direction.normalized().into(),
+            sun_color: (self.sun.color * self.sun.intensity).into(),
+            ambient_color: (self.ambient_color * self.ambient_intensity).into(),
+            point_lights,
+            point_light_count: self.point_lights.len().min(MAX_POINT_LIGHTS) as u32,
+            _padding: [0; 3],
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::Dependencies,
+    reflect::{self, UniformBlock},
+    texture::{Texture, TextureOptions},
+    State,
+};
+
+/// How a material treats the alpha of its albedo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    Opaque,
+    /// Texels below `ALPHA_CUTOFF` are cut out, with alpha-to-coverage in multisampled passes.
+    Cutout,
+    /// Alpha blended, drawn sorted into the transparent pass.
+    Blended,
+}
+
+pub const ALPHA_CUTOFF: f32 = 0.5;
+
+// Matches the `Material` block of the shaders. The mode values select the shader branch.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    alpha_cutoff: f32,
+    alpha_mode: u32,
+    _padding: [u32; 2],
+}
+
+impl MaterialUniform {
+    fn new(alpha_mode: AlphaMode, sample_count: u32) -> Self {
+        Self {
+            alpha_cutoff: ALPHA_CUTOFF,
+            alpha_mode: match alpha_mode {
+                AlphaMode::Opaque => 0,
+                AlphaMode::Cutout if sample_count > 1 => 2,
+                AlphaMode::Cutout => 1,
+                AlphaMode::Blended => 3,
+            },
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Layout of the material bind group (set 1): albedo texture array, sampler and material
+/// uniforms.
+pub(crate) fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("material_bind_group_layout"),
+    })
+}
+
+/// The material uniforms, for checking shaders against them.
+pub fn uniform_block() -> UniformBlock {
+    let material: MaterialUniform = bytemuck::Zeroable::zeroed();
+    UniformBlock::of::<MaterialUniform>(1, 2).with_members(vec![
+        (
+            "u_alpha_cutoff",
+            reflect::member_offset(&material, &material.alpha_cutoff),
+        ),
+        (
+            "u_alpha_mode",
+            reflect::member_offset(&material, &material.alpha_mode),
+        ),
+    ])
+}
+
+fn create_bind_group(
+    state: &State,
+    albedo: &Texture,
+    uniform_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: state.material_bind_group_layout(),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&albedo.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&albedo.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("material_bind_group"),
+    })
+}
+
+/// Textures bound at set 1. The albedo is multiplied with the vertex color, every material
+/// samples it as an array so single images and layered ones share a layout.
+pub struct Material {
+    albedo_paths: Vec<PathBuf>,
+    albedo: Texture,
+    alpha_mode: AlphaMode,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    /// Loads the albedo as a texture array with one layer per path. `sample_count` is the one
+    /// of the pass the material is drawn in, which decides how cutouts are antialiased.
+    pub fn load<P: AsRef<Path>>(
+        state: &State,
+        albedo_layers: &[P],
+        alpha_mode: AlphaMode,
+        sample_count: u32,
+    ) -> Self {
+        let albedo_paths: Vec<PathBuf> = albedo_layers
+            .iter()
+            .map(|path| path.as_ref().to_owned())
+            .collect();
+        let albedo = Texture::create_array(
+            &state.device,
+            &state.queue,
+            &albedo_paths,
+            &TextureOptions::default(),
+        )
+        .unwrap_or_else(|err| {
+            log::error!("Failed to load texture array {:?}: {}", albedo_paths, err);
+            Texture::create_fallback(
+                &state.device,
+                &state.queue,
+                wgpu::TextureViewDimension::D2Array,
+            )
+        });
+        let uniform_buffer = state
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Material Buffer"),
+                contents: bytemuck::bytes_of(&MaterialUniform::new(alpha_mode, sample_count)),
+                usage: wgpu::BufferUsage::UNIFORM,
+            });
+        let bind_group = create_bind_group(state, &albedo, &uniform_buffer);
+        Self {
+            albedo_paths,
+            albedo,
+            alpha_mode,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn dependencies(&self) -> Dependencies {
+        self.albedo_paths
+            .iter()
+            .fold(Dependencies::default(), Dependencies::with_file)
+    }
+
+    /// Reloads the textures found in `changed`. Returns true when the bind group was recreated.
+    pub fn files_changed(&mut self, state: &State, changed: &HashSet<PathBuf>) -> bool {
+        if !self.dependencies().any_changed(changed) {
+            return false;
+        }
+        // A texture that fails to load keeps the previous one.
+        self.albedo = match Texture::create_array(
+            &state.device,
+            &state.queue,
+            &self.albedo_paths,
+            &TextureOptions::default(),
+        ) {
+            Ok(albedo) => albedo,
+            Err(err) => {
+                log::error!(
+                    "Failed to reload texture array {:?}: {}",
+                    self.albedo_paths,
+                    err
+                );
+                return false;
+            }
+        };
+        self.bind_group = create_bind_group(state, &self.albedo, &self.uniform_buffer);
+        log::info!("Texture array {:?} reloaded.", self.albedo_paths);
+        true
+    }
+}
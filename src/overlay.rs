@@ -0,0 +1,367 @@
+//! Shader errors drawn over the frame, so a broken shader shows up where the result is watched
+//! rather than only in the log.
+
+use std::collections::BTreeMap;
+
+use image::GrayImage;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    assets::AssetOwner,
+    pipeline::{BlendMode, PipelineBuilder, PipelineError, ShaderError},
+};
+
+/// The printable ASCII glyphs, 16 per row starting at the space. The glyphs are the public domain
+/// 6x10 font of X11 misc-fixed.
+const FONT_PATH: &str = "assets/fonts/font_6x10.png";
+const GLYPH_WIDTH: u32 = 6;
+const GLYPH_HEIGHT: u32 = 10;
+const GLYPHS_PER_ROW: u32 = 16;
+
+/// Screen pixels per font pixel.
+const SCALE: u32 = 2;
+/// Distance of the overlay from the window corner, in screen pixels.
+const MARGIN: u32 = 8;
+/// Border around the text, in font pixels.
+const PADDING: u32 = 2;
+
+const VERTEX_SHADER: &str = "assets/shaders/overlay.vert.wgsl";
+const FRAGMENT_SHADER: &str = "assets/shaders/overlay.frag.wgsl";
+
+/// Errors reported by each asset owner. The errors of a reload replace the previous ones of the
+/// owner, so they are shown until it reloads without errors.
+#[derive(Debug, Default)]
+pub(crate) struct ShaderErrors {
+    errors: BTreeMap<Option<AssetOwner>, Vec<ShaderError>>,
+    owner: Option<AssetOwner>,
+    reloaded: Option<Vec<ShaderError>>,
+}
+
+impl ShaderErrors {
+    /// Attributes the following reports to `owner`, replacing its errors when `reload` is set.
+    pub(crate) fn begin(&mut self, owner: AssetOwner, reload: bool) {
+        self.owner = Some(owner);
+        self.reloaded = if reload { Some(Vec::new()) } else { None };
+    }
+
+    pub(crate) fn end(&mut self) {
+        if let Some(errors) = self.reloaded.take() {
+            if errors.is_empty() {
+                self.errors.remove(&self.owner);
+            } else {
+                self.errors.insert(self.owner, errors);
+            }
+        }
+        self.owner = None;
+    }
+
+    pub(crate) fn report(&mut self, error: &PipelineError) {
+        let errors = match self.reloaded {
+            Some(ref mut errors) => errors,
+            None => self.errors.entry(self.owner).or_default(),
+        };
+        for error in error.shader_errors() {
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &ShaderError> {
+        self.errors.values().flatten()
+    }
+}
+
+struct Text {
+    lines: Vec<String>,
+    width: u32,
+    height: u32,
+    bind_group: wgpu::BindGroup,
+}
+
+struct Renderer {
+    font: GrayImage,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    rect_buffer: wgpu::Buffer,
+    text: Option<Text>,
+}
+
+/// Draws the shader errors into the swap chain after the render graph ran.
+#[derive(Default)]
+pub(crate) struct ErrorOverlay {
+    renderer: Option<Renderer>,
+    /// Set when the renderer couldn't be created, so it isn't tried again every frame.
+    failed: bool,
+}
+
+/// Breaks `text` into lines of at most `columns` characters. Continuation lines are indented.
+fn wrap(text: &str, columns: usize, lines: &mut Vec<String>) {
+    let chars: Vec<char> = text
+        .chars()
+        .map(|c| if c == '\t' { ' ' } else { c })
+        .collect();
+    let mut rest = &chars[..];
+    let mut indent = "";
+    loop {
+        let width = columns.saturating_sub(indent.len()).max(1);
+        let (line, next) = rest.split_at(width.min(rest.len()));
+        lines.push(format!("{}{}", indent, line.iter().collect::<String>()));
+        if next.is_empty() {
+            break;
+        }
+        rest = next;
+        indent = "    ";
+    }
+}
+
+fn layout(errors: &[String], screen: (u32, u32)) -> Vec<String> {
+    let available = |size: u32, glyph: u32| {
+        (size.saturating_sub(2 * MARGIN) / SCALE).saturating_sub(2 * PADDING) / glyph
+    };
+    let columns = available(screen.0, GLYPH_WIDTH).max(1) as usize;
+    let rows = available(screen.1, GLYPH_HEIGHT).max(1) as usize;
+    let mut lines = Vec::new();
+    for error in errors {
+        for line in error.lines() {
+            wrap(line, columns, &mut lines);
+        }
+    }
+    if lines.len() > rows {
+        let hidden = lines.len() - rows + 1;
+        lines.truncate(rows - 1);
+        lines.push(format!("... {} more lines", hidden));
+    }
+    lines
+}
+
+/// Glyph coverage of `lines`, one byte per font pixel.
+fn rasterize(font: &GrayImage, lines: &[String]) -> (u32, u32, Vec<u8>) {
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+    let width = columns * GLYPH_WIDTH + 2 * PADDING;
+    let height = lines.len() as u32 * GLYPH_HEIGHT + 2 * PADDING;
+    let mut pixels = vec![0; (width * height) as usize];
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let glyph = match c {
+                ' '..='\u{7f}' => c as u32 - ' ' as u32,
+                _ => '?' as u32 - ' ' as u32,
+            };
+            let glyph_x = glyph % GLYPHS_PER_ROW * GLYPH_WIDTH;
+            let glyph_y = glyph / GLYPHS_PER_ROW * GLYPH_HEIGHT;
+            let x = PADDING + column as u32 * GLYPH_WIDTH;
+            let y = PADDING + row as u32 * GLYPH_HEIGHT;
+            if glyph_x + GLYPH_WIDTH > font.width() || glyph_y + GLYPH_HEIGHT > font.height() {
+                continue;
+            }
+            for dy in 0..GLYPH_HEIGHT {
+                for dx in 0..GLYPH_WIDTH {
+                    if font.get_pixel(glyph_x + dx, glyph_y + dy)[0] > 127 {
+                        pixels[((y + dy) * width + x + dx) as usize] = 255;
+                    }
+                }
+            }
+        }
+    }
+    (width, height, pixels)
+}
+
+impl Renderer {
+    fn create(device: &wgpu::Device, format: wgpu::TextureFormat) -> Result<Self, String> {
+        let font = image::open(FONT_PATH)
+            .map_err(|err| format!("failed to load {}: {}", FONT_PATH, err))?
+            .to_luma8();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("overlay_bind_group_layout"),
+        });
+
+        let pipeline = PipelineBuilder::new(VERTEX_SHADER)
+            .with_label("Overlay Pipeline")
+            .with_fragment_shader(FRAGMENT_SHADER)
+            .with_bind_group_layouts(&[&bind_group_layout])
+            .with_color_formats(&[format])
+            .with_blend_mode(BlendMode::Alpha)
+            .with_topology(wgpu::PrimitiveTopology::TriangleStrip)
+            .build(device)
+            .map_err(|err| err.to_string())?;
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Overlay Sampler"),
+            ..Default::default()
+        });
+
+        let rect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Rect Buffer"),
+            contents: bytemuck::cast_slice(&[0.0f32; 4]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        Ok(Self {
+            font,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            rect_buffer,
+            text: None,
+        })
+    }
+
+    fn set_text(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lines: Vec<String>) {
+        if self.text.as_ref().map_or(false, |text| text.lines == lines) {
+            return;
+        }
+        let (width, height, pixels) = rasterize(&self.font, &lines);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Overlay Text"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &pixels,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: width,
+                rows_per_image: height,
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.rect_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("overlay_bind_group"),
+        });
+        self.text = Some(Text {
+            lines,
+            width,
+            height,
+            bind_group,
+        });
+    }
+}
+
+impl ErrorOverlay {
+    pub(crate) fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::SwapChainDescriptor,
+        view: &wgpu::TextureView,
+        errors: &[String],
+    ) {
+        if errors.is_empty() || self.failed {
+            return;
+        }
+        if self.renderer.is_none() {
+            match Renderer::create(device, target.format) {
+                Ok(renderer) => self.renderer = Some(renderer),
+                Err(err) => {
+                    log::error!("Failed to create the error overlay: {}", err);
+                    self.failed = true;
+                    return;
+                }
+            }
+        }
+        let renderer = match self.renderer {
+            Some(ref mut renderer) => renderer,
+            None => return,
+        };
+
+        renderer.set_text(device, queue, layout(errors, (target.width, target.height)));
+        let text = match renderer.text {
+            Some(ref text) => text,
+            None => return,
+        };
+        let to_clip = |pixels: u32, size: u32| 2.0 * pixels as f32 / size.max(1) as f32;
+        let left = -1.0 + to_clip(MARGIN, target.width);
+        let top = 1.0 - to_clip(MARGIN, target.height);
+        let rect = [
+            left,
+            top,
+            left + to_clip(text.width * SCALE, target.width),
+            top - to_clip(text.height * SCALE, target.height),
+        ];
+        queue.write_buffer(&renderer.rect_buffer, 0, bytemuck::cast_slice(&rect));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("overlay"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&renderer.pipeline);
+        render_pass.set_bind_group(0, &text.bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+    }
+}
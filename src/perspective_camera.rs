@@ -14,12 +14,11 @@ pub struct PerspectiveCamera {
 
 impl PerspectiveCamera {
     pub fn compute_matrix(&self) -> Mat4 {
-        return // * 
-            perspective(
-                self.vertical_fov,
-                self.aspect_ratio,
-                self.z_near,
-                self.z_far,
-            ) * Mat4::look_at(self.eye, self.at, self.up);
+        perspective(
+            self.vertical_fov,
+            self.aspect_ratio,
+            self.z_near,
+            self.z_far,
+        ) * Mat4::look_at(self.eye, self.at, self.up)
     }
 }
@@ -1,235 +1,935 @@
-use std::{borrow::Cow, path::Path};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    fmt,
+    path::{Path, PathBuf},
+};
 
-use shaderc::CompileOptions;
+#[cfg(feature = "shader-compiler")]
+use shaderc::{CompileOptions, IncludeCallbackResult, ResolvedInclude};
 use wgpu::VertexBufferLayout;
 
-use crate::texture::Texture;
+use crate::{
+    reflect::{self, UniformBlock},
+    render_graph::{PassFormats, PassId},
+    shader_cache,
+};
 
-pub fn compile_shader<'a>(
-    source_text: &str,
-    shader_kind: shaderc::ShaderKind,
-    input_file_name: &str,
-    entry_point_name: &str,
-    additional_options: Option<&CompileOptions>,
-) -> Option<wgpu::ShaderSource<'a>> {
-    let mut compiler = shaderc::Compiler::new()?;
+/// Directory that `#include` paths are resolved against.
+pub const SHADER_DIR: &str = "assets/shaders";
 
-    let result = compiler.compile_into_spirv(
-        source_text,
-        shader_kind,
-        input_file_name,
-        entry_point_name,
-        additional_options,
-    );
+#[cfg(feature = "shader-compiler")]
+fn resolve_include(requested: &str) -> IncludeCallbackResult {
+    let path = Path::new(SHADER_DIR).join(requested);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => Ok(ResolvedInclude {
+            resolved_name: path.to_string_lossy().into_owned(),
+            content,
+        }),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
 
-    match result {
-        Ok(artifact) => {
-            log::info!(
-                "Compiled \'{}\' with {} warnings.",
-                input_file_name,
-                artifact.get_num_warnings()
-            );
-            let vec: Vec<u32> = artifact.as_binary().into();
-            Some(wgpu::ShaderSource::SpirV(Cow::from(vec)))
-        }
-        Err(error) => {
-            log::error!("{:?}", error);
-            None
+/// `#include` lines accept both `"file"` and `<file>`, either way relative to `SHADER_DIR`.
+fn parse_include(line: &str) -> Option<&str> {
+    let name = line.trim().strip_prefix("#include")?.trim();
+    name.strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .or_else(|| name.strip_prefix('<')?.strip_suffix('>'))
+}
+
+fn collect_includes(path: &Path, includes: &mut Vec<PathBuf>) {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    for name in source.lines().filter_map(parse_include) {
+        let include = Path::new(SHADER_DIR).join(name);
+        if !includes.contains(&include) {
+            includes.push(include.clone());
+            collect_includes(&include, includes);
         }
     }
 }
 
-pub fn create_default_pipeline<P: AsRef<Path>>(
-    device: &wgpu::Device,
-    sc_desc: &wgpu::SwapChainDescriptor,
-    bind_group_layouts: &[&wgpu::BindGroupLayout],
-    vertex_buffers_layout: &[VertexBufferLayout],
-    vs_path: P,
-    fs_path: P,
-) -> Option<wgpu::RenderPipeline> {
-    let vs_src = std::fs::read_to_string(&vs_path).ok()?;
-    let vs_data = compile_shader(
-        &vs_src,
-        shaderc::ShaderKind::Vertex,
-        vs_path.as_ref().to_str().unwrap_or("vertex_shader.unknown"),
-        "main",
-        None,
-    )?;
-
-    let fs_src = std::fs::read_to_string(&fs_path).ok()?;
-    let fs_data = compile_shader(
-        &fs_src,
-        shaderc::ShaderKind::Fragment,
-        fs_path
-            .as_ref()
-            .to_str()
-            .unwrap_or("fragment_shader.unknown"),
-        "main",
-        None,
-    )?;
+/// Every file the shader at `path` includes, directly or through other includes.
+pub fn shader_includes<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {
+    let mut includes = Vec::new();
+    collect_includes(path.as_ref(), &mut includes);
+    includes
+}
 
-    let vs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-        label: Some("Vertex Shader"),
-        source: vs_data,
-        flags: wgpu::ShaderFlags::default(),
-    });
+/// Preprocessor defines a shader is compiled with, such as `WIND_ENABLED` or `LOD_LEVEL=2`.
+/// Kept sorted, so equal sets compare and hash equal and can key shader permutations.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines(BTreeMap<String, Option<String>>);
 
-    let fs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-        label: Some("Fragment Shader"),
-        source: fs_data,
-        flags: wgpu::ShaderFlags::default(),
-    });
+impl ShaderDefines {
+    pub fn with(mut self, name: &str) -> Self {
+        self.0.insert(name.to_owned(), None);
+        self
+    }
 
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Render Pipeline Layout"),
-        bind_group_layouts,
-        push_constant_ranges: &[],
-    });
+    pub fn with_value<T: ToString>(mut self, name: &str, value: T) -> Self {
+        self.0.insert(name.to_owned(), Some(value.to_string()));
+        self
+    }
+
+    pub fn without(mut self, name: &str) -> Self {
+        self.0.remove(name);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
 
-    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &vs_module,
-            entry_point: "main",
-            buffers: vertex_buffers_layout,
-        },
-        fragment: Some(wgpu::FragmentState {
-            // 3.
-            module: &fs_module,
-            entry_point: "main",
-            targets: &[wgpu::ColorTargetState {
-                // 4.
-                format: sc_desc.format,
-                alpha_blend: wgpu::BlendState::REPLACE,
-                color_blend: wgpu::BlendState::REPLACE,
-                write_mask: wgpu::ColorWrite::ALL,
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.0.get(name)?.as_deref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_deref()))
+    }
+
+    #[cfg(feature = "shader-compiler")]
+    fn compile_options(&self) -> Option<CompileOptions<'static>> {
+        let mut options = CompileOptions::new()?;
+        for (name, value) in &self.0 {
+            options.add_macro_definition(name, value.as_deref());
+        }
+        Some(options)
+    }
+}
+
+#[derive(Debug)]
+struct Bundles<T> {
+    render: Option<T>,
+    shadow: Option<T>,
+}
+
+impl<T> Default for Bundles<T> {
+    fn default() -> Self {
+        Self {
+            render: None,
+            shadow: None,
+        }
+    }
+}
+
+/// The render and shadow bundles of a pipeline handler for each set of defines it was drawn
+/// with, so switching `State::shader_defines` back and forth builds each permutation once.
+/// Bundles are made by a `create_bundle(defines, pass)` closure, and only for the shadow pass
+/// when the defines contain `SHADOWS`.
+#[derive(Debug)]
+pub struct PermutationCache<T = wgpu::RenderBundle> {
+    /// Defines of the bundles in use.
+    defines: ShaderDefines,
+    /// Bundles of every define set used since the last reload.
+    bundles: HashMap<ShaderDefines, Bundles<T>>,
+}
+
+impl<T> Default for PermutationCache<T> {
+    fn default() -> Self {
+        Self {
+            defines: ShaderDefines::default(),
+            bundles: HashMap::new(),
+        }
+    }
+}
+
+impl<T> PermutationCache<T> {
+    pub fn defines(&self) -> &ShaderDefines {
+        &self.defines
+    }
+
+    /// Switches to the bundles for `defines`, building them for `draw_pass` and the shadow pass
+    /// the first time.
+    pub fn select(
+        &mut self,
+        defines: &ShaderDefines,
+        draw_pass: PassId,
+        mut create_bundle: impl FnMut(&ShaderDefines, PassId) -> Option<T>,
+    ) {
+        self.defines = defines.clone();
+        if self.bundles.contains_key(defines) {
+            return;
+        }
+        let bundles = Bundles {
+            render: create_bundle(defines, draw_pass),
+            shadow: if defines.contains("SHADOWS") {
+                create_bundle(defines, PassId::SHADOW)
+            } else {
+                None
+            },
+        };
+        self.bundles.insert(defines.clone(), bundles);
+    }
+
+    /// Rebuilds the bundles in use, the shadow one only if `shadow` is set, keeping the previous
+    /// ones where that fails. Bundles of other define sets are dropped and built again when
+    /// selected. `name` is for the log.
+    pub fn reload(
+        &mut self,
+        name: &str,
+        draw_pass: PassId,
+        shadow: bool,
+        mut create_bundle: impl FnMut(&ShaderDefines, PassId) -> Option<T>,
+    ) {
+        let mut bundles = self.bundles.remove(&self.defines).unwrap_or_default();
+        self.bundles.clear();
+        let bundle = create_bundle(&self.defines, draw_pass);
+        if bundle.is_some() {
+            bundles.render = bundle;
+            log::info!("{} bundle reloaded.", name);
+        }
+        if shadow && self.defines.contains("SHADOWS") {
+            let bundle = create_bundle(&self.defines, PassId::SHADOW);
+            if bundle.is_some() {
+                bundles.shadow = bundle;
+                log::info!("{} shadow bundle reloaded.", name);
+            }
+        }
+        self.bundles.insert(self.defines.clone(), bundles);
+    }
+
+    /// The bundle of the defines in use for `pass`, which is the shadow pass or the draw pass.
+    pub fn bundle(&self, pass: PassId) -> Option<&T> {
+        let bundles = self.bundles.get(&self.defines)?;
+        if pass == PassId::SHADOW {
+            bundles.shadow.as_ref()
+        } else {
+            bundles.render.as_ref()
+        }
+    }
+}
+
+/// A compile error or pipeline mismatch, located in the shader or include it comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderError {
+    pub path: PathBuf,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PipelineError {
+    /// A shader couldn't be read.
+    Io(PathBuf, std::io::Error),
+    /// A shader failed to compile.
+    Compile(Vec<ShaderError>),
+    /// A compiled shader doesn't match the vertex buffers or uniform blocks of its pipeline.
+    Mismatch(Vec<ShaderError>),
+    /// A shader is neither in the shader cache nor can it be compiled.
+    NoCompiler(PathBuf),
+    /// wgpu rejected the pipeline built from the vertex shader at the path, e.g. as a binding
+    /// of its shaders doesn't match the bind group layouts.
+    Validation(PathBuf, String),
+}
+
+impl PipelineError {
+    /// The errors to show for each shader, in the order they were reported.
+    pub fn shader_errors(&self) -> Vec<ShaderError> {
+        match self {
+            PipelineError::Io(path, err) => vec![ShaderError {
+                path: path.clone(),
+                line: None,
+                message: err.to_string(),
             }],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw, // 2.
-            cull_mode: wgpu::CullMode::None,
-            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-            polygon_mode: wgpu::PolygonMode::Fill,
-        },
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less, // 1.
-            stencil: wgpu::StencilState::default(),     // 2.
-            bias: wgpu::DepthBiasState::default(),
-            // Setting this to true requires Features::DEPTH_CLAMPING
-            clamp_depth: false,
-        }),
-        multisample: wgpu::MultisampleState {
-            count: Texture::MSAA_SAMPLES,
-            ..Default::default()
-        },
+            PipelineError::Compile(errors) | PipelineError::Mismatch(errors) => errors.clone(),
+            PipelineError::NoCompiler(path) => vec![ShaderError {
+                path: path.clone(),
+                line: None,
+                message: "not in the shader cache and shaderc is unavailable".into(),
+            }],
+            PipelineError::Validation(path, message) => vec![ShaderError {
+                path: path.clone(),
+                line: None,
+                message: message.clone(),
+            }],
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (what, errors) = match self {
+            PipelineError::Io(path, err) => {
+                return write!(f, "failed to read shader {:?}: {}", path, err)
+            }
+            PipelineError::Compile(errors) => ("failed to compile shader", errors),
+            PipelineError::Mismatch(errors) => ("shader doesn't match its pipeline", errors),
+            PipelineError::NoCompiler(path) => {
+                return write!(
+                    f,
+                    "no SPIR-V for {:?} in {:?} and shaders can't be compiled without the \
+                     shader-compiler feature",
+                    path,
+                    shader_cache::SHADER_CACHE_DIR
+                )
+            }
+            PipelineError::Validation(path, message) => {
+                return write!(f, "invalid pipeline for {:?}: {}", path, message)
+            }
+        };
+        write!(f, "{}", what)?;
+        for error in errors {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::Io(_, err) => Some(err),
+            PipelineError::Compile(_)
+            | PipelineError::Mismatch(_)
+            | PipelineError::NoCompiler(_)
+            | PipelineError::Validation(..) => None,
+        }
+    }
+}
+
+thread_local! {
+    /// Validation errors raised on this thread while an error scope is open.
+    static ERROR_SCOPE: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Lets pipeline creation catch the validation errors of `device`, which wgpu raises from the
+/// call that caused them. Other errors still abort like wgpu's default handler.
+pub fn capture_validation_errors(device: &wgpu::Device) {
+    device.on_uncaptured_error(|error| {
+        if let wgpu::Error::ValidationError {
+            ref description, ..
+        } = error
+        {
+            let captured = ERROR_SCOPE.with(|scope| match *scope.borrow_mut() {
+                Some(ref mut errors) => {
+                    errors.push(description.clone());
+                    true
+                }
+                None => false,
+            });
+            if captured {
+                return;
+            }
+        }
+        log::error!("wgpu error: {}", error);
+        panic!("Handling wgpu errors as fatal by default");
     });
+}
 
-    Some(pipeline)
+/// Runs `f` and returns the validation errors it raised instead of aborting on them.
+fn with_error_scope<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let outer = ERROR_SCOPE.with(|scope| scope.replace(Some(Vec::new())));
+    let value = f();
+    let errors = ERROR_SCOPE.with(|scope| scope.replace(outer));
+    (value, errors.unwrap_or_default())
 }
 
-#[allow(dead_code)]
-pub fn create_transparent_pipeline<P: AsRef<Path>>(
-    device: &wgpu::Device,
-    sc_desc: &wgpu::SwapChainDescriptor,
-    bind_group_layouts: &[&wgpu::BindGroupLayout],
-    vertex_buffers_layout: &[VertexBufferLayout],
-    vs_path: P,
-    fs_path: P,
-) -> Option<wgpu::RenderPipeline> {
-    let vs_src = std::fs::read_to_string(&vs_path).ok()?;
-    let vs_data = compile_shader(
-        &vs_src,
-        shaderc::ShaderKind::Vertex,
-        vs_path.as_ref().to_str().unwrap_or("vertex_shader.unknown"),
-        "main",
-        None,
-    )?;
-
-    let fs_src = std::fs::read_to_string(&fs_path).ok()?;
-    let fs_data = compile_shader(
-        &fs_src,
-        shaderc::ShaderKind::Fragment,
-        fs_path
-            .as_ref()
-            .to_str()
-            .unwrap_or("fragment_shader.unknown"),
+/// Splits the log of a failed compile into its errors. glslang reports them as
+/// `file:line: error: message`, naming includes by the path `resolve_include` gave them.
+#[cfg(feature = "shader-compiler")]
+fn parse_compile_errors(path: &Path, log: &str) -> Vec<ShaderError> {
+    let errors: Vec<ShaderError> = log
+        .lines()
+        .filter_map(|line| {
+            let (location, message) = line.split_once(": error: ")?;
+            let (file, line) = match location.rsplit_once(':') {
+                Some((file, line)) => (file, line.trim().parse().ok()),
+                None => (location, None),
+            };
+            Some(ShaderError {
+                path: PathBuf::from(file),
+                line,
+                message: message.trim().to_owned(),
+            })
+        })
+        .collect();
+    if errors.is_empty() {
+        vec![ShaderError {
+            path: path.to_owned(),
+            line: None,
+            message: log.trim().to_owned(),
+        }]
+    } else {
+        errors
+    }
+}
+
+#[cfg(feature = "shader-compiler")]
+fn compile_error(path: &Path, error: shaderc::Error) -> PipelineError {
+    let errors = match error {
+        shaderc::Error::CompilationError(_, log) => parse_compile_errors(path, &log),
+        shaderc::Error::InternalError(message)
+        | shaderc::Error::InvalidStage(message)
+        | shaderc::Error::InvalidAssembly(message)
+        | shaderc::Error::NullResultObject(message) => vec![ShaderError {
+            path: path.to_owned(),
+            line: None,
+            message,
+        }],
+    };
+    PipelineError::Compile(errors)
+}
+
+/// Loads the shader at `path`. WGSL, recognized by the `.wgsl` extension, goes to wgpu as is and
+/// takes no defines. GLSL is loaded as SPIR-V from the shader cache when it holds this version
+/// of the source, includes and defines, and compiled and cached otherwise.
+pub fn compile_shader_file<'a>(
+    path: &Path,
+    stage: wgpu::ShaderStage,
+    defines: &ShaderDefines,
+) -> Result<wgpu::ShaderSource<'a>, PipelineError> {
+    let source =
+        std::fs::read_to_string(path).map_err(|err| PipelineError::Io(path.to_owned(), err))?;
+    if path.extension() == Some(OsStr::new("wgsl")) {
+        return Ok(wgpu::ShaderSource::Wgsl(Cow::from(source)));
+    }
+    let key = shader_cache::key(path, &source, stage, defines);
+    if let Some(words) = shader_cache::load(path, key) {
+        log::info!("Loaded '{}' from the shader cache.", path.display());
+        return Ok(wgpu::ShaderSource::SpirV(Cow::from(words)));
+    }
+    let words = compile_glsl(path, &source, stage, defines)?;
+    #[cfg(feature = "shader-compiler")]
+    shader_cache::store(path, key, &words);
+    Ok(wgpu::ShaderSource::SpirV(Cow::from(words)))
+}
+
+#[cfg(feature = "shader-compiler")]
+fn compile_glsl(
+    path: &Path,
+    source: &str,
+    stage: wgpu::ShaderStage,
+    defines: &ShaderDefines,
+) -> Result<Vec<u32>, PipelineError> {
+    let shader_kind = match stage {
+        wgpu::ShaderStage::VERTEX => shaderc::ShaderKind::Vertex,
+        wgpu::ShaderStage::FRAGMENT => shaderc::ShaderKind::Fragment,
+        wgpu::ShaderStage::COMPUTE => shaderc::ShaderKind::Compute,
+        _ => {
+            let message = format!("needs a single stage, got {:?}", stage);
+            return Err(PipelineError::Compile(vec![ShaderError {
+                path: path.to_owned(),
+                line: None,
+                message,
+            }]));
+        }
+    };
+    let options = defines
+        .compile_options()
+        .ok_or_else(|| PipelineError::NoCompiler(path.to_owned()))?;
+    compile_to_spirv(
+        source,
+        shader_kind,
+        &path.to_string_lossy(),
         "main",
-        None,
-    )?;
+        Some(&options),
+    )
+    .map_err(|err| err.unwrap_or_else(|| PipelineError::NoCompiler(path.to_owned())))
+}
 
-    let vs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-        label: Some("Vertex Shader"),
-        source: vs_data,
-        flags: wgpu::ShaderFlags::default(),
-    });
+#[cfg(not(feature = "shader-compiler"))]
+fn compile_glsl(
+    path: &Path,
+    _source: &str,
+    _stage: wgpu::ShaderStage,
+    _defines: &ShaderDefines,
+) -> Result<Vec<u32>, PipelineError> {
+    Err(PipelineError::NoCompiler(path.to_owned()))
+}
 
-    let fs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-        label: Some("Fragment Shader"),
-        source: fs_data,
-        flags: wgpu::ShaderFlags::default(),
+/// Fails with `None` when shaderc itself can't be set up.
+#[cfg(feature = "shader-compiler")]
+fn compile_to_spirv(
+    source_text: &str,
+    shader_kind: shaderc::ShaderKind,
+    input_file_name: &str,
+    entry_point_name: &str,
+    additional_options: Option<&CompileOptions>,
+) -> Result<Vec<u32>, Option<PipelineError>> {
+    let mut compiler = shaderc::Compiler::new().ok_or(None)?;
+    let mut options = match additional_options {
+        Some(options) => options.clone().ok_or(None)?,
+        None => CompileOptions::new().ok_or(None)?,
+    };
+    options.set_include_callback(|requested, _include_type, _requesting_source, _depth| {
+        resolve_include(requested)
     });
 
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Render Pipeline Layout"),
-        bind_group_layouts,
-        push_constant_ranges: &[],
-    });
+    let artifact = compiler
+        .compile_into_spirv(
+            source_text,
+            shader_kind,
+            input_file_name,
+            entry_point_name,
+            Some(&options),
+        )
+        .map_err(|err| Some(compile_error(Path::new(input_file_name), err)))?;
+    log::info!(
+        "Compiled \'{}\' with {} warnings.",
+        input_file_name,
+        artifact.get_num_warnings()
+    );
+    Ok(artifact.as_binary().into())
+}
+
+/// Loads a shader like `compile_shader_file` and checks its inputs and uniform blocks against the
+/// pipeline, so a mismatch is reported before wgpu sees it.
+fn load_shader<'a>(
+    path: &Path,
+    stage: wgpu::ShaderStage,
+    defines: &ShaderDefines,
+    vertex_buffers_layout: &[VertexBufferLayout],
+    uniform_blocks: &[UniformBlock],
+) -> Result<wgpu::ShaderSource<'a>, PipelineError> {
+    let source = compile_shader_file(path, stage, defines)?;
+    reflect::check_shader(path, &source, stage, vertex_buffers_layout, uniform_blocks)?;
+    Ok(source)
+}
+
+/// How fragment colors are written into the color targets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites the target.
+    Replace,
+    /// Blends by source alpha, for geometry drawn back to front.
+    Alpha,
+}
 
-    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &vs_module,
-            entry_point: "main",
-            buffers: vertex_buffers_layout,
-        },
-        fragment: Some(wgpu::FragmentState {
-            // 3.
-            module: &fs_module,
-            entry_point: "main",
-            targets: &[wgpu::ColorTargetState {
-                // 4.
-                format: sc_desc.format,
-                color_blend: wgpu::BlendState {
+impl BlendMode {
+    fn color_target(self, format: wgpu::TextureFormat) -> wgpu::ColorTargetState {
+        let (color_blend, alpha_blend) = match self {
+            BlendMode::Replace => (wgpu::BlendState::REPLACE, wgpu::BlendState::REPLACE),
+            BlendMode::Alpha => (
+                wgpu::BlendState {
                     src_factor: wgpu::BlendFactor::SrcAlpha,
                     dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
                     operation: wgpu::BlendOperation::Add,
                 },
-                alpha_blend: wgpu::BlendState {
+                wgpu::BlendState {
                     src_factor: wgpu::BlendFactor::One,
-                    dst_factor: wgpu::BlendFactor::One,
-                    operation: wgpu::BlendOperation::Max,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
                 },
-                write_mask: wgpu::ColorWrite::ALL,
-            }],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+            ),
+        };
+        wgpu::ColorTargetState {
+            format,
+            alpha_blend,
+            color_blend,
+            write_mask: wgpu::ColorWrite::ALL,
+        }
+    }
+}
+
+/// Render pipeline settings, with the shaders loaded and checked against the pipeline by
+/// `build`. The defaults are an opaque, depth tested and depth writing triangle list without
+/// culling.
+#[derive(Clone, Debug)]
+pub struct PipelineBuilder<'a> {
+    label: &'a str,
+    vertex_shader: PathBuf,
+    fragment_shader: Option<PathBuf>,
+    defines: ShaderDefines,
+    bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    vertex_buffers: &'a [VertexBufferLayout<'a>],
+    uniform_blocks: &'a [UniformBlock],
+    push_constant_ranges: &'a [wgpu::PushConstantRange],
+    color_formats: Vec<wgpu::TextureFormat>,
+    depth_format: Option<wgpu::TextureFormat>,
+    sample_count: u32,
+    blend_mode: BlendMode,
+    alpha_to_coverage_enabled: bool,
+    topology: wgpu::PrimitiveTopology,
+    strip_index_format: Option<wgpu::IndexFormat>,
+    front_face: wgpu::FrontFace,
+    cull_mode: wgpu::CullMode,
+    polygon_mode: wgpu::PolygonMode,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+    depth_bias: wgpu::DepthBiasState,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn new<P: AsRef<Path>>(vertex_shader: P) -> Self {
+        Self {
+            label: "Render Pipeline",
+            vertex_shader: vertex_shader.as_ref().to_owned(),
+            fragment_shader: None,
+            defines: ShaderDefines::default(),
+            bind_group_layouts: &[],
+            vertex_buffers: &[],
+            uniform_blocks: &[],
+            push_constant_ranges: &[],
+            color_formats: Vec::new(),
+            depth_format: None,
+            sample_count: 1,
+            blend_mode: BlendMode::Replace,
+            alpha_to_coverage_enabled: false,
+            topology: wgpu::PrimitiveTopology::TriangleList,
             strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw, // 2.
+            front_face: wgpu::FrontFace::Ccw,
             cull_mode: wgpu::CullMode::None,
-            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            // Anything other than Fill requires Features::NON_FILL_POLYGON_MODE.
             polygon_mode: wgpu::PolygonMode::Fill,
-        },
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: Texture::DEPTH_FORMAT,
             depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less, // 1.
-            stencil: wgpu::StencilState::default(),     // 2.
-            bias: wgpu::DepthBiasState::default(),
-            // Setting this to true requires Features::DEPTH_CLAMPING
-            clamp_depth: false,
-        }),
-        multisample: wgpu::MultisampleState {
-            count: Texture::MSAA_SAMPLES,
-            ..Default::default()
-        },
-    });
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_bias: wgpu::DepthBiasState::default(),
+        }
+    }
+
+    pub fn with_label(mut self, label: &'a str) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// Without a fragment shader the pipeline only writes depth.
+    pub fn with_fragment_shader<P: AsRef<Path>>(mut self, fragment_shader: P) -> Self {
+        self.fragment_shader = Some(fragment_shader.as_ref().to_owned());
+        self
+    }
+
+    pub fn with_defines(mut self, defines: &ShaderDefines) -> Self {
+        self.defines = defines.clone();
+        self
+    }
+
+    pub fn with_bind_group_layouts(
+        mut self,
+        bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    ) -> Self {
+        self.bind_group_layouts = bind_group_layouts;
+        self
+    }
+
+    pub fn with_vertex_buffers(mut self, vertex_buffers: &'a [VertexBufferLayout<'a>]) -> Self {
+        self.vertex_buffers = vertex_buffers;
+        self
+    }
+
+    /// Uniform buffers the shaders are checked against, see `reflect`.
+    pub fn with_uniform_blocks(mut self, uniform_blocks: &'a [UniformBlock]) -> Self {
+        self.uniform_blocks = uniform_blocks;
+        self
+    }
+
+    /// Requires `Features::PUSH_CONSTANTS`.
+    pub fn with_push_constant_ranges(
+        mut self,
+        push_constant_ranges: &'a [wgpu::PushConstantRange],
+    ) -> Self {
+        self.push_constant_ranges = push_constant_ranges;
+        self
+    }
+
+    /// Takes the attachment formats and sample count of a render graph pass.
+    pub fn with_formats(mut self, formats: &PassFormats) -> Self {
+        self.color_formats = formats.color_formats.clone();
+        self.depth_format = formats.depth_format;
+        self.sample_count = formats.sample_count;
+        self
+    }
+
+    pub fn with_color_formats(mut self, color_formats: &[wgpu::TextureFormat]) -> Self {
+        self.color_formats = color_formats.to_vec();
+        self
+    }
+
+    pub fn with_depth_format(mut self, depth_format: Option<wgpu::TextureFormat>) -> Self {
+        self.depth_format = depth_format;
+        self
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// Turns the fragment alpha into sample coverage. Only has an effect when multisampled.
+    pub fn with_alpha_to_coverage(mut self, alpha_to_coverage_enabled: bool) -> Self {
+        self.alpha_to_coverage_enabled = alpha_to_coverage_enabled;
+        self
+    }
+
+    /// Strip topologies drawn with an index buffer also need `with_strip_index_format`.
+    pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn with_strip_index_format(mut self, strip_index_format: wgpu::IndexFormat) -> Self {
+        self.strip_index_format = Some(strip_index_format);
+        self
+    }
+
+    pub fn with_front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn with_cull_mode(mut self, cull_mode: wgpu::CullMode) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Anything other than `Fill` requires `Features::NON_FILL_POLYGON_MODE`.
+    pub fn with_polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn with_depth_write(mut self, depth_write_enabled: bool) -> Self {
+        self.depth_write_enabled = depth_write_enabled;
+        self
+    }
+
+    pub fn with_depth_compare(mut self, depth_compare: wgpu::CompareFunction) -> Self {
+        self.depth_compare = depth_compare;
+        self
+    }
+
+    pub fn with_depth_bias(mut self, depth_bias: wgpu::DepthBiasState) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    /// Depth-only settings for shadow passes, applied after `with_formats`. The depth bias keeps
+    /// surfaces from shadowing themselves.
+    pub fn for_shadow_casting(self) -> Self {
+        self.with_label("Depth Pipeline")
+            .with_color_formats(&[])
+            .with_depth_compare(wgpu::CompareFunction::LessEqual)
+            .with_depth_bias(wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            })
+    }
+
+    /// Loads the shaders and creates the pipeline.
+    pub fn build(&self, device: &wgpu::Device) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let vs_data = load_shader(
+            &self.vertex_shader,
+            wgpu::ShaderStage::VERTEX,
+            &self.defines,
+            self.vertex_buffers,
+            self.uniform_blocks,
+        )?;
+        let fs_data = match &self.fragment_shader {
+            Some(fragment_shader) => Some(load_shader(
+                fragment_shader,
+                wgpu::ShaderStage::FRAGMENT,
+                &self.defines,
+                self.vertex_buffers,
+                self.uniform_blocks,
+            )?),
+            None => None,
+        };
+
+        // An edited shader can compile and still not fit the pipeline, which wgpu only reports
+        // as a validation error. The caller keeps its previous pipeline then.
+        let (pipeline, errors) = with_error_scope(|| {
+            let vs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Vertex Shader"),
+                source: vs_data,
+                flags: wgpu::ShaderFlags::default(),
+            });
+
+            let fs_module = fs_data.map(|fs_data| {
+                device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                    label: Some("Fragment Shader"),
+                    source: fs_data,
+                    flags: wgpu::ShaderFlags::default(),
+                })
+            });
 
-    Some(pipeline)
+            let layout_label = format!("{} Layout", self.label);
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&layout_label),
+                bind_group_layouts: self.bind_group_layouts,
+                push_constant_ranges: self.push_constant_ranges,
+            });
+
+            let color_targets: Vec<_> = self
+                .color_formats
+                .iter()
+                .map(|&format| self.blend_mode.color_target(format))
+                .collect();
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(self.label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &vs_module,
+                    entry_point: "main",
+                    buffers: self.vertex_buffers,
+                },
+                fragment: fs_module.as_ref().map(|module| wgpu::FragmentState {
+                    module,
+                    entry_point: "main",
+                    targets: &color_targets,
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: self.topology,
+                    strip_index_format: self.strip_index_format,
+                    front_face: self.front_face,
+                    cull_mode: self.cull_mode,
+                    polygon_mode: self.polygon_mode,
+                },
+                depth_stencil: self.depth_format.map(|format| wgpu::DepthStencilState {
+                    format,
+                    depth_write_enabled: self.depth_write_enabled,
+                    depth_compare: self.depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: self.depth_bias.clone(),
+                    // Setting this to true requires Features::DEPTH_CLAMPING
+                    clamp_depth: false,
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: self.sample_count,
+                    alpha_to_coverage_enabled: self.alpha_to_coverage_enabled,
+                    ..Default::default()
+                },
+            })
+        });
+        if !errors.is_empty() {
+            return Err(PipelineError::Validation(
+                self.vertex_shader.clone(),
+                errors.join("\n"),
+            ));
+        }
+
+        Ok(pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defines(names: &str) -> ShaderDefines {
+        names
+            .split_whitespace()
+            .fold(ShaderDefines::default(), ShaderDefines::with)
+    }
+
+    /// Stands in for a bundle, naming the permutation and pass it was built for.
+    fn bundle_name(defines: &ShaderDefines, pass: PassId) -> String {
+        let names: Vec<&str> = defines.iter().map(|(name, _)| name).collect();
+        let pass = if pass == PassId::SHADOW {
+            "shadow"
+        } else {
+            "main"
+        };
+        format!("{} {}", names.join(" "), pass)
+    }
+
+    #[test]
+    fn permutations_are_built_once() {
+        let built = RefCell::new(Vec::new());
+        let create_bundle = |defines: &ShaderDefines, pass| {
+            built.borrow_mut().push(bundle_name(defines, pass));
+            Some(bundle_name(defines, pass))
+        };
+        let mut cache = PermutationCache::default();
+        cache.select(&defines("SHADOWS"), PassId::MAIN, create_bundle);
+        cache.select(&defines("WIND"), PassId::MAIN, create_bundle);
+        cache.select(&defines("SHADOWS"), PassId::MAIN, create_bundle);
+        assert_eq!(
+            *built.borrow(),
+            ["SHADOWS main", "SHADOWS shadow", "WIND main"]
+        );
+        assert_eq!(cache.defines(), &defines("SHADOWS"));
+        assert_eq!(cache.bundle(PassId::MAIN).unwrap(), "SHADOWS main");
+        assert_eq!(cache.bundle(PassId::SHADOW).unwrap(), "SHADOWS shadow");
+
+        cache.select(&defines("WIND"), PassId::MAIN, create_bundle);
+        assert_eq!(cache.bundle(PassId::SHADOW), None);
+    }
+
+    #[test]
+    fn failed_reloads_keep_the_previous_bundles() {
+        let built = RefCell::new(Vec::new());
+        let mut cache = PermutationCache::default();
+        cache.select(&defines("WIND"), PassId::MAIN, |defines, pass| {
+            Some(bundle_name(defines, pass))
+        });
+        cache.select(&defines("SHADOWS"), PassId::MAIN, |defines, pass| {
+            Some(bundle_name(defines, pass))
+        });
+        cache.reload("Test", PassId::MAIN, false, |defines, pass| {
+            built.borrow_mut().push(bundle_name(defines, pass));
+            None
+        });
+        assert_eq!(*built.borrow(), ["SHADOWS main"]);
+        assert_eq!(cache.bundle(PassId::MAIN).unwrap(), "SHADOWS main");
+        assert_eq!(cache.bundle(PassId::SHADOW).unwrap(), "SHADOWS shadow");
+
+        // Other permutations were dropped and are built from the reloaded shaders.
+        cache.select(&defines("WIND"), PassId::MAIN, |defines, pass| {
+            built.borrow_mut().push(bundle_name(defines, pass));
+            Some(bundle_name(defines, pass))
+        });
+        assert_eq!(*built.borrow(), ["SHADOWS main", "WIND main"]);
+    }
+
+    #[cfg(feature = "shader-compiler")]
+    #[test]
+    fn compile_errors_name_file_and_line() {
+        let log = "assets/shaders/grass.vert:12: error: 'wind' : undeclared identifier\n\
+                   assets/shaders/include/light.glsl:40: error: '' :  syntax error, unexpected \
+                   RIGHT_PAREN\n\
+                   2 errors generated.\n";
+        let errors = parse_compile_errors(Path::new("assets/shaders/grass.vert"), log);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].path, Path::new("assets/shaders/grass.vert"));
+        assert_eq!(errors[0].line, Some(12));
+        assert_eq!(errors[0].message, "'wind' : undeclared identifier");
+        assert_eq!(
+            errors[1].path,
+            Path::new("assets/shaders/include/light.glsl")
+        );
+        assert_eq!(errors[1].line, Some(40));
+        assert_eq!(
+            errors[1].message,
+            "'' :  syntax error, unexpected RIGHT_PAREN"
+        );
+    }
+
+    #[cfg(feature = "shader-compiler")]
+    #[test]
+    fn compile_errors_without_location_keep_the_log() {
+        let log = "grass.vert: error: #version: versions before 150 do not allow a profile token\n";
+        let errors = parse_compile_errors(Path::new("grass.vert"), log);
+        assert_eq!(errors[0].path, Path::new("grass.vert"));
+        assert_eq!(errors[0].line, None);
+
+        let errors = parse_compile_errors(Path::new("grass.vert"), "out of memory\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, Path::new("grass.vert"));
+        assert_eq!(errors[0].line, None);
+        assert_eq!(errors[0].message, "out of memory");
+    }
 }
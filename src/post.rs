@@ -0,0 +1,728 @@
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    pipeline::PipelineBuilder,
+    reflect::UniformBlock,
+    render_graph::{ColorAttachment, PassDescriptor, PassFormats, TargetDescriptor, TargetSize},
+    texture::{Texture, TextureOptions},
+    Dependencies, PassId, PipelineHandler, State, TargetId,
+};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Bloom {
+    /// HDR brightness above which pixels start to bloom.
+    pub threshold: f32,
+    pub intensity: f32,
+    /// Blur radius in texels of the half resolution bloom targets.
+    pub radius: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.3,
+            radius: 1.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ColorGrading {
+    /// A 3D LUT stored as a strip of N slices of NxN texels, blue increasing from left to right.
+    /// It is indexed with sRGB encoded colors, values above 1 keep their excess.
+    pub lut: PathBuf,
+    /// Blend between the input (0) and the graded color (1).
+    pub strength: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            lut: PathBuf::from("assets/luts/neutral.png"),
+            strength: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Vignette {
+    pub intensity: f32,
+    /// Distance from the center, 1 being the corners, where darkening starts.
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            intensity: 0.4,
+            radius: 0.6,
+            smoothness: 0.4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum PostEffectKind {
+    Bloom(Bloom),
+    ColorGrading(ColorGrading),
+    Vignette(Vignette),
+}
+
+/// An entry of the post effect stack in `State`. Effects are applied in stack order to the HDR
+/// scene color before tone mapping.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PostEffect {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub kind: PostEffectKind,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl PostEffect {
+    pub fn new(kind: PostEffectKind) -> Self {
+        Self {
+            enabled: true,
+            kind,
+        }
+    }
+}
+
+const PING: TargetId = TargetId("post_ping");
+const PONG: TargetId = TargetId("post_pong");
+const BLOOM_A: TargetId = TargetId("bloom_a");
+const BLOOM_B: TargetId = TargetId("bloom_b");
+const BLOOM_DIVISOR: u32 = 2;
+
+// Filtering across mip levels or wrapped edges would blend neighbouring LUT slices.
+const LUT_OPTIONS: TextureOptions = TextureOptions {
+    mag_filter: wgpu::FilterMode::Linear,
+    min_filter: wgpu::FilterMode::Linear,
+    mipmap_filter: wgpu::FilterMode::Nearest,
+    address_mode_u: wgpu::AddressMode::ClampToEdge,
+    address_mode_v: wgpu::AddressMode::ClampToEdge,
+    anisotropy: 1,
+    generate_mipmaps: false,
+};
+
+// Passes are handed out in order to the passes of the enabled effects.
+const PASSES: [PassId; 16] = [
+    PassId("post_0"),
+    PassId("post_1"),
+    PassId("post_2"),
+    PassId("post_3"),
+    PassId("post_4"),
+    PassId("post_5"),
+    PassId("post_6"),
+    PassId("post_7"),
+    PassId("post_8"),
+    PassId("post_9"),
+    PassId("post_10"),
+    PassId("post_11"),
+    PassId("post_12"),
+    PassId("post_13"),
+    PassId("post_14"),
+    PassId("post_15"),
+];
+
+const VERTEX_SHADER: &str = "assets/shaders/fullscreen.vert";
+const COPY_SHADER: &str = "assets/shaders/post_copy.wgsl";
+const BLOOM_THRESHOLD_SHADER: &str = "assets/shaders/bloom_threshold.frag";
+const BLOOM_BLUR_SHADER: &str = "assets/shaders/bloom_blur.frag";
+const BLOOM_COMPOSITE_SHADER: &str = "assets/shaders/bloom_composite.frag";
+const COLOR_GRADING_SHADER: &str = "assets/shaders/color_grading.frag";
+const VIGNETTE_SHADER: &str = "assets/shaders/vignette.frag";
+
+/// Second texture bound to a post pass.
+#[derive(Clone, Debug)]
+enum Aux {
+    Input,
+    Target(TargetId),
+    Lut(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+struct PostPass {
+    shader: &'static str,
+    input: TargetId,
+    aux: Aux,
+    output: TargetId,
+    params: [f32; 4],
+}
+
+impl PostPass {
+    fn new(shader: &'static str, input: TargetId, output: TargetId, params: [f32; 4]) -> Self {
+        Self {
+            shader,
+            input,
+            aux: Aux::Input,
+            output,
+            params,
+        }
+    }
+}
+
+// Effects ping-pong between two targets, reading the scene from `TargetId::HDR` and writing the
+// last one back into it for the tonemap pass.
+fn plan_passes(effects: &[PostEffect]) -> Vec<PostPass> {
+    let enabled: Vec<&PostEffectKind> = effects
+        .iter()
+        .filter(|effect| effect.enabled)
+        .map(|effect| &effect.kind)
+        .collect();
+    let mut passes = Vec::new();
+
+    let mut input = TargetId::HDR;
+    if enabled.len() == 1 {
+        // A single effect can't read and write `HDR` at the same time.
+        passes.push(PostPass::new(COPY_SHADER, input, PING, [0.0; 4]));
+        input = PING;
+    }
+
+    for (index, kind) in enabled.iter().enumerate() {
+        let output = if index + 1 == enabled.len() {
+            TargetId::HDR
+        } else if input == PING {
+            PONG
+        } else {
+            PING
+        };
+
+        match kind {
+            PostEffectKind::Bloom(bloom) => {
+                passes.push(PostPass::new(
+                    BLOOM_THRESHOLD_SHADER,
+                    input,
+                    BLOOM_A,
+                    [bloom.threshold, 0.0, 0.0, 0.0],
+                ));
+                passes.push(PostPass::new(
+                    BLOOM_BLUR_SHADER,
+                    BLOOM_A,
+                    BLOOM_B,
+                    [bloom.radius, 1.0, 0.0, 0.0],
+                ));
+                passes.push(PostPass::new(
+                    BLOOM_BLUR_SHADER,
+                    BLOOM_B,
+                    BLOOM_A,
+                    [bloom.radius, 0.0, 1.0, 0.0],
+                ));
+                passes.push(PostPass {
+                    aux: Aux::Target(BLOOM_A),
+                    ..PostPass::new(
+                        BLOOM_COMPOSITE_SHADER,
+                        input,
+                        output,
+                        [bloom.intensity, 0.0, 0.0, 0.0],
+                    )
+                });
+            }
+            PostEffectKind::ColorGrading(grading) => passes.push(PostPass {
+                aux: Aux::Lut(grading.lut.clone()),
+                ..PostPass::new(
+                    COLOR_GRADING_SHADER,
+                    input,
+                    output,
+                    [grading.strength, 0.0, 0.0, 0.0],
+                )
+            }),
+            PostEffectKind::Vignette(vignette) => passes.push(PostPass::new(
+                VIGNETTE_SHADER,
+                input,
+                output,
+                [
+                    vignette.intensity,
+                    vignette.radius,
+                    vignette.smoothness,
+                    0.0,
+                ],
+            )),
+        }
+
+        input = output;
+    }
+
+    passes
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler {
+            filtering: true,
+            comparison: false,
+        },
+        count: None,
+    };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            texture_entry(0),
+            sampler_entry(1),
+            texture_entry(2),
+            sampler_entry(3),
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("post_bind_group_layout"),
+    })
+}
+
+fn create_pipeline(
+    state: &State,
+    formats: &PassFormats,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    shader: &str,
+) -> Option<wgpu::RenderPipeline> {
+    let pipeline = PipelineBuilder::new(VERTEX_SHADER)
+        .with_fragment_shader(shader)
+        .with_formats(formats)
+        .with_bind_group_layouts(&[bind_group_layout])
+        .with_uniform_blocks(&[
+            UniformBlock::of::<[f32; 4]>(0, 4).with_members(vec![("u_params", 0)])
+        ])
+        .build(&state.device);
+    state.report_errors(pipeline)
+}
+
+fn create_bundle(
+    state: &State,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    pipeline: &wgpu::RenderPipeline,
+    luts: &HashMap<PathBuf, Texture>,
+    id: PassId,
+    pass: &PostPass,
+) -> Option<wgpu::RenderBundle> {
+    let graph = state.render_graph();
+    let input = graph.target(pass.input)?;
+    let aux = match pass.aux {
+        Aux::Input => input,
+        Aux::Target(target) => graph.target(target)?,
+        Aux::Lut(ref path) => luts.get(path)?,
+    };
+
+    let params_buffer = state
+        .device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Params Buffer"),
+            contents: bytemuck::cast_slice(&pass.params),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+    let bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&input.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&aux.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&aux.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("post_bind_group"),
+    });
+
+    let formats = state.pass_formats(id)?;
+    let mut encoder = formats.create_bundle_encoder(&state.device, None);
+
+    encoder.set_pipeline(pipeline);
+    encoder.set_bind_group(0, &bind_group, &[]);
+    encoder.draw(0..3, 0..1);
+
+    Some(encoder.finish(&wgpu::RenderBundleDescriptor { label: Some(id.0) }))
+}
+
+/// Runs the post effect stack of `State` between the main and the tonemap pass. Changes to the
+/// stack are picked up on the next frame.
+pub struct PostProcessPipeline {
+    bind_group_layout: wgpu::BindGroupLayout,
+    applied: Option<Vec<PostEffect>>,
+    passes: Vec<(PassId, PostPass)>,
+    pipelines: HashMap<&'static str, wgpu::RenderPipeline>,
+    luts: HashMap<PathBuf, Texture>,
+    bundles: HashMap<PassId, wgpu::RenderBundle>,
+}
+
+impl PostProcessPipeline {
+    /// The graph is only extended on the first `update`, as creation has no mutable `State`.
+    pub fn create(state: &State) -> Self {
+        Self {
+            bind_group_layout: create_bind_group_layout(&state.device),
+            applied: None,
+            passes: Vec::new(),
+            pipelines: HashMap::new(),
+            luts: HashMap::new(),
+            bundles: HashMap::new(),
+        }
+    }
+
+    fn add_targets(state: &mut State) {
+        for &(id, size) in &[
+            (PING, TargetSize::SwapChain),
+            (PONG, TargetSize::SwapChain),
+            (
+                BLOOM_A,
+                TargetSize::SwapChainDivided {
+                    divisor: BLOOM_DIVISOR,
+                },
+            ),
+            (
+                BLOOM_B,
+                TargetSize::SwapChainDivided {
+                    divisor: BLOOM_DIVISOR,
+                },
+            ),
+        ] {
+            state.add_render_target(
+                id,
+                TargetDescriptor {
+                    format: Texture::HDR_FORMAT,
+                    sample_count: 1,
+                    size,
+                },
+            );
+        }
+    }
+
+    fn rebuild(&mut self, state: &mut State) {
+        if self.applied.is_none() {
+            Self::add_targets(state);
+        }
+        for (id, _) in self.passes.drain(..) {
+            state.remove_render_pass(id);
+        }
+
+        let effects = state.post_effects().to_vec();
+        let mut passes = plan_passes(&effects);
+        if passes.len() > PASSES.len() {
+            log::error!(
+                "Post effect stack needs {} passes, at most {} are supported.",
+                passes.len(),
+                PASSES.len()
+            );
+            passes.clear();
+        }
+
+        let mut previous = PassId::TRANSPARENT;
+        for (&id, pass) in PASSES.iter().zip(passes.iter()) {
+            state.add_render_pass(PassDescriptor {
+                id,
+                color_attachments: vec![ColorAttachment {
+                    target: pass.output,
+                    resolve_target: None,
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                }],
+                depth_attachment: None,
+                dependencies: vec![previous],
+            });
+            previous = id;
+        }
+        if let Some(tonemap) = state.render_graph().pass(PassId::TONEMAP) {
+            let mut tonemap = tonemap.clone();
+            tonemap
+                .dependencies
+                .retain(|dependency| !PASSES.contains(dependency));
+            if !tonemap.dependencies.contains(&previous) {
+                tonemap.dependencies.push(previous);
+            }
+            state.add_render_pass(tonemap);
+        }
+
+        for pass in passes.iter() {
+            if let Aux::Lut(ref path) = pass.aux {
+                if !self.luts.contains_key(path) {
+                    let lut = Texture::create(&state.device, &state.queue, path, &LUT_OPTIONS)
+                        .unwrap_or_else(|err| {
+                            log::error!("Failed to load LUT {:?}: {}", path, err);
+                            Texture::create_fallback(
+                                &state.device,
+                                &state.queue,
+                                wgpu::TextureViewDimension::D2,
+                            )
+                        });
+                    self.luts.insert(path.clone(), lut);
+                }
+            }
+        }
+
+        self.passes = PASSES.iter().copied().zip(passes).collect();
+        self.applied = Some(effects);
+        self.create_bundles(state);
+    }
+
+    /// Fragment shaders of the planned passes, including those that failed to build, so fixing
+    /// them is picked up.
+    fn shaders(&self) -> Vec<&'static str> {
+        let mut shaders: Vec<&'static str> =
+            self.passes.iter().map(|(_, pass)| pass.shader).collect();
+        shaders.sort_unstable();
+        shaders.dedup();
+        shaders
+    }
+
+    fn formats(&self, state: &State) -> Option<PassFormats> {
+        let &(id, _) = self.passes.first()?;
+        state.pass_formats(id)
+    }
+
+    fn create_bundles(&mut self, state: &State) {
+        self.bundles.clear();
+        let formats = match self.formats(state) {
+            Some(formats) => formats,
+            None => return,
+        };
+
+        for (_, pass) in self.passes.iter() {
+            if !self.pipelines.contains_key(pass.shader) {
+                if let Some(pipeline) =
+                    create_pipeline(state, &formats, &self.bind_group_layout, pass.shader)
+                {
+                    self.pipelines.insert(pass.shader, pipeline);
+                }
+            }
+        }
+
+        for (id, pass) in self.passes.iter() {
+            let bundle = self.pipelines.get(pass.shader).and_then(|pipeline| {
+                create_bundle(
+                    state,
+                    &self.bind_group_layout,
+                    pipeline,
+                    &self.luts,
+                    *id,
+                    pass,
+                )
+            });
+            match bundle {
+                Some(bundle) => {
+                    self.bundles.insert(*id, bundle);
+                }
+                None => log::error!("Post pass {:?} ({}) is skipped.", id, pass.shader),
+            }
+        }
+    }
+}
+
+impl PipelineHandler for PostProcessPipeline {
+    fn passes(&self) -> &[PassId] {
+        &PASSES
+    }
+
+    fn update(&mut self, state: &mut State) {
+        if self.applied.as_deref() != Some(state.post_effects()) {
+            self.rebuild(state);
+        }
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        let shaders = self
+            .shaders()
+            .into_iter()
+            .fold(Dependencies::default(), Dependencies::with_shader);
+        self.luts
+            .keys()
+            .fold(shaders.with_shader(VERTEX_SHADER), Dependencies::with_file)
+    }
+
+    fn files_changed(&mut self, state: &mut State, changed: &HashSet<PathBuf>) {
+        let vertex_changed = Dependencies::default()
+            .with_shader(VERTEX_SHADER)
+            .any_changed(changed);
+        // Shaders without a pipeline failed to build before and are retried as well.
+        let shaders: Vec<&'static str> = self
+            .shaders()
+            .into_iter()
+            .filter(|shader| {
+                vertex_changed
+                    || !self.pipelines.contains_key(shader)
+                    || Dependencies::default()
+                        .with_shader(shader)
+                        .any_changed(changed)
+            })
+            .collect();
+        let luts: Vec<PathBuf> = self
+            .luts
+            .keys()
+            .filter(|lut| Dependencies::default().with_file(lut).any_changed(changed))
+            .cloned()
+            .collect();
+        if shaders.is_empty() && luts.is_empty() {
+            return;
+        }
+
+        if let Some(formats) = self.formats(state) {
+            // A shader that fails to compile keeps its previous pipeline.
+            for shader in shaders {
+                if let Some(pipeline) =
+                    create_pipeline(state, &formats, &self.bind_group_layout, shader)
+                {
+                    self.pipelines.insert(shader, pipeline);
+                    log::info!("Post shader {} reloaded.", shader);
+                }
+            }
+        }
+        // A LUT that fails to load keeps its previous texture.
+        for path in luts {
+            match Texture::create(&state.device, &state.queue, &path, &LUT_OPTIONS) {
+                Ok(lut) => {
+                    self.luts.insert(path, lut);
+                }
+                Err(err) => log::error!("Failed to reload LUT {:?}: {}", path, err),
+            }
+        }
+        self.create_bundles(state);
+    }
+
+    fn resized(&mut self, state: &State) {
+        self.create_bundles(state);
+    }
+
+    fn render_bundle(&mut self, _state: &State, pass: PassId) -> Option<&wgpu::RenderBundle> {
+        self.bundles.get(&pass)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vignette() -> PostEffect {
+        PostEffect::new(PostEffectKind::Vignette(Vignette::default()))
+    }
+
+    fn targets(passes: &[PostPass]) -> Vec<(&'static str, TargetId, TargetId)> {
+        passes
+            .iter()
+            .map(|pass| (pass.shader, pass.input, pass.output))
+            .collect()
+    }
+
+    #[test]
+    fn no_enabled_effects_plan_no_passes() {
+        assert!(plan_passes(&[]).is_empty());
+        let disabled = PostEffect {
+            enabled: false,
+            ..vignette()
+        };
+        assert!(plan_passes(&[disabled]).is_empty());
+    }
+
+    #[test]
+    fn single_effect_copies_the_scene_first() {
+        assert_eq!(
+            targets(&plan_passes(&[vignette()])),
+            [
+                (COPY_SHADER, TargetId::HDR, PING),
+                (VIGNETTE_SHADER, PING, TargetId::HDR),
+            ]
+        );
+    }
+
+    #[test]
+    fn even_effect_counts_ping_pong_back_into_hdr() {
+        assert_eq!(
+            targets(&plan_passes(&[vignette(), vignette()])),
+            [
+                (VIGNETTE_SHADER, TargetId::HDR, PING),
+                (VIGNETTE_SHADER, PING, TargetId::HDR),
+            ]
+        );
+        assert_eq!(
+            targets(&plan_passes(&vec![vignette(); 4])),
+            [
+                (VIGNETTE_SHADER, TargetId::HDR, PING),
+                (VIGNETTE_SHADER, PING, PONG),
+                (VIGNETTE_SHADER, PONG, PING),
+                (VIGNETTE_SHADER, PING, TargetId::HDR),
+            ]
+        );
+    }
+
+    #[test]
+    fn odd_effect_counts_ping_pong_back_into_hdr() {
+        assert_eq!(
+            targets(&plan_passes(&vec![vignette(); 3])),
+            [
+                (VIGNETTE_SHADER, TargetId::HDR, PING),
+                (VIGNETTE_SHADER, PING, PONG),
+                (VIGNETTE_SHADER, PONG, TargetId::HDR),
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_effects_are_skipped() {
+        let disabled = PostEffect {
+            enabled: false,
+            ..vignette()
+        };
+        assert_eq!(
+            targets(&plan_passes(&[vignette(), disabled, vignette()])),
+            [
+                (VIGNETTE_SHADER, TargetId::HDR, PING),
+                (VIGNETTE_SHADER, PING, TargetId::HDR),
+            ]
+        );
+    }
+
+    #[test]
+    fn bloom_blurs_in_its_own_targets() {
+        let bloom = PostEffect::new(PostEffectKind::Bloom(Bloom::default()));
+        let passes = plan_passes(&[bloom, vignette()]);
+        assert_eq!(
+            targets(&passes),
+            [
+                (BLOOM_THRESHOLD_SHADER, TargetId::HDR, BLOOM_A),
+                (BLOOM_BLUR_SHADER, BLOOM_A, BLOOM_B),
+                (BLOOM_BLUR_SHADER, BLOOM_B, BLOOM_A),
+                (BLOOM_COMPOSITE_SHADER, TargetId::HDR, PING),
+                (VIGNETTE_SHADER, PING, TargetId::HDR),
+            ]
+        );
+        assert!(matches!(passes[3].aux, Aux::Target(BLOOM_A)));
+    }
+}
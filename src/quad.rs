@@ -3,7 +3,11 @@ use std::{collections::HashSet, path::PathBuf};
 use ultraviolet::{Mat4, Vec2, Vec3, Vec4};
 use wgpu::util::DeviceExt as _;
 
-use crate::{pipeline::create_default_pipeline, texture::Texture, PipelineHandler, State};
+use crate::{
+    material::{self, AlphaMode, Material},
+    pipeline::{PermutationCache, PipelineBuilder, ShaderDefines},
+    Dependencies, PassId, PipelineHandler, State,
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -71,9 +75,9 @@ const VERTICES: &[Vertex] = &[
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Instance {
-    mat: Mat4,
-    color: Vec4,
+pub struct Instance {
+    pub mat: Mat4,
+    pub color: Vec4,
 }
 
 impl Instance {
@@ -108,99 +112,169 @@ impl Instance {
     }
 }
 
+const ALBEDO_PATH: &str = "assets/textures/ground.png";
+const VERTEX_SHADER: &str = "assets/shaders/quad.vert";
+const FRAGMENT_SHADER: &str = "assets/shaders/blinn_phong.frag";
+
+fn shader_dependencies() -> Dependencies {
+    Dependencies::default()
+        .with_shader(VERTEX_SHADER)
+        .with_shader(FRAGMENT_SHADER)
+}
 
 fn create_bundle(
     state: &State,
+    instances: &[Instance],
+    material: &Material,
+    defines: &ShaderDefines,
+    pass: PassId,
 ) -> Option<wgpu::RenderBundle> {
-
     let device = &state.device;
     let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(&VERTICES),
+        contents: bytemuck::cast_slice(VERTICES),
         usage: wgpu::BufferUsage::VERTEX,
     });
 
-    let instances = [Instance {
-        mat: Mat4::from_scale(2.0),
-        color: Vec4::new(20.0 / 255.0, 40.0 / 255.0, 0.0, 1.0),
-    }];
-
     let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Instance Buffer"),
-        contents: bytemuck::cast_slice(&instances),
+        contents: bytemuck::cast_slice(instances),
         usage: wgpu::BufferUsage::VERTEX,
     });
 
-    let pipeline = create_default_pipeline(
-        &state.device,
-        &state.sc_desc,
-        &[&state.uniform_bind_group_layout],
-        &[
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                step_mode: wgpu::InputStepMode::Vertex,
-                attributes: &Vertex::attributes(),
-            },
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
-                step_mode: wgpu::InputStepMode::Instance,
-                attributes: &Instance::attributes(),
-            },
-        ],
-        "assets/shaders/quad.vert",
-        "assets/shaders/blinn_phong.frag",
-    )?;
-
-    let mut encoder =
-        state
-            .device
-            .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
-                label: None,
-                color_formats: &[state.sc_desc.format],
-                depth_stencil_format: Some(Texture::DEPTH_FORMAT),
-                sample_count: Texture::MSAA_SAMPLES,
-            });
+    let formats = state.pass_formats(pass)?;
+    let bind_group_layouts = [
+        state.pass_bind_group_layout(pass),
+        state.material_bind_group_layout(),
+    ];
+    let vertex_buffers_layout = [
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &Vertex::attributes(),
+        },
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &Instance::attributes(),
+        },
+    ];
+    let mut uniform_blocks = state.pass_uniform_blocks(pass);
+    if pass != PassId::SHADOW {
+        uniform_blocks.push(material::uniform_block());
+    }
+    let builder = PipelineBuilder::new(VERTEX_SHADER)
+        .with_defines(defines)
+        .with_formats(&formats)
+        .with_vertex_buffers(&vertex_buffers_layout)
+        .with_uniform_blocks(&uniform_blocks);
+    let builder = if pass == PassId::SHADOW {
+        builder
+            .for_shadow_casting()
+            .with_bind_group_layouts(&bind_group_layouts[..1])
+    } else {
+        builder
+            .with_fragment_shader(FRAGMENT_SHADER)
+            .with_bind_group_layouts(&bind_group_layouts)
+    };
+    let pipeline = state.report_errors(builder.build(&state.device))?;
+
+    let mut encoder = formats.create_bundle_encoder(&state.device, None);
 
     encoder.set_pipeline(&pipeline);
     encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
     encoder.set_vertex_buffer(1, instance_buffer.slice(..));
-    encoder.set_bind_group(0, &state.uniform_bind_group, &[]);
+    encoder.set_bind_group(0, state.pass_bind_group(pass), &[]);
+    if pass != PassId::SHADOW {
+        encoder.set_bind_group(1, material.bind_group(), &[]);
+    }
 
     encoder.draw(0..VERTICES.len() as _, 0..instances.len() as _);
 
     Some(encoder.finish(&wgpu::RenderBundleDescriptor {
-        label: Some("quad"),
+        label: Some(if pass == PassId::SHADOW {
+            "quad_shadow"
+        } else {
+            "quad"
+        }),
     }))
 }
 
-
 pub struct QuadPipeline {
-    render_bundle: Option<wgpu::RenderBundle>,
+    instances: Vec<Instance>,
+    material: Material,
+    /// Bundles of every define set used since the last reload, following
+    /// `State::shader_defines`.
+    bundles: PermutationCache,
 }
 
 impl QuadPipeline {
     pub fn create(state: &State) -> Self {
-        Self {
-            render_bundle: create_bundle(state),
-        }
+        Self::with_instances(
+            state,
+            vec![Instance {
+                mat: Mat4::from_scale(2.0),
+                color: Vec4::new(20.0 / 255.0, 40.0 / 255.0, 0.0, 1.0),
+            }],
+        )
+    }
+
+    pub fn with_instances(state: &State, instances: Vec<Instance>) -> Self {
+        let sample_count = state
+            .pass_formats(PassId::MAIN)
+            .map_or(1, |formats| formats.sample_count);
+        let material = Material::load(state, &[ALBEDO_PATH], AlphaMode::Opaque, sample_count);
+        let mut pipeline = Self {
+            instances,
+            material,
+            bundles: PermutationCache::default(),
+        };
+        pipeline.select_defines(state);
+        pipeline
+    }
+
+    /// Switches to the bundles for the defines of `state`, building them the first time.
+    fn select_defines(&mut self, state: &State) {
+        let (instances, material) = (&self.instances, &self.material);
+        self.bundles
+            .select(state.shader_defines(), PassId::MAIN, |defines, pass| {
+                create_bundle(state, instances, material, defines, pass)
+            });
     }
 
+    fn reload(&mut self, state: &State, shadow: bool) {
+        let (instances, material) = (&self.instances, &self.material);
+        self.bundles
+            .reload("Quad", PassId::MAIN, shadow, |defines, pass| {
+                create_bundle(state, instances, material, defines, pass)
+            });
+    }
 }
 
 impl PipelineHandler for QuadPipeline {
+    fn dependencies(&self) -> Dependencies {
+        shader_dependencies().with_dependencies(self.material.dependencies())
+    }
+
+    fn update(&mut self, state: &mut State) {
+        if self.bundles.defines() != state.shader_defines() {
+            self.select_defines(state);
+        }
+    }
+
     fn files_changed(&mut self, state: &mut State, changed: &HashSet<PathBuf>) {
-        if changed.iter().any(|path| {
-            path.ends_with("assets/shaders/quad.vert") || path.ends_with("assets/shaders/blinn_phong.frag")
-        }) {
-            let bundle = create_bundle(state);
-            if bundle.is_some() {
-                self.render_bundle = bundle;
-                log::info!("Quad bundle reloaded.");
-            }
+        let shaders_changed = shader_dependencies().any_changed(changed);
+        let material_changed = self.material.files_changed(state, changed);
+        if shaders_changed || material_changed {
+            self.reload(state, shaders_changed);
         }
     }
 
-    fn render_bundle(&mut self, _state: &State) -> Option<&wgpu::RenderBundle> {
-        return self.render_bundle.as_ref();
+    fn passes(&self) -> &[PassId] {
+        &[PassId::SHADOW, PassId::MAIN]
+    }
+
+    fn render_bundle(&mut self, _state: &State, pass: PassId) -> Option<&wgpu::RenderBundle> {
+        self.bundles.bundle(pass)
     }
 }
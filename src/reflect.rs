@@ -0,0 +1,752 @@
+//! Checks compiled shaders against the Rust side of their pipeline: vertex inputs against the
+//! `VertexBufferLayout`s and uniform blocks against the structs uploaded to them.
+//!
+//! Only SPIR-V is reflected, by reading its decorations, types and global variables. WGSL goes
+//! through naga inside wgpu, which validates it already. naga's SPIR-V front end drops the member
+//! offsets that uniform blocks are sized by, so the few instructions needed are parsed here.
+
+use std::{collections::HashMap, path::Path};
+
+use wgpu::{VertexBufferLayout, VertexFormat};
+
+use crate::pipeline::{PipelineError, ShaderError};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+
+/// A uniform buffer of the pipeline and the layout of the Rust struct written to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UniformBlock {
+    pub group: u32,
+    pub binding: u32,
+    pub size: u64,
+    /// The Rust type, for error messages.
+    pub type_name: &'static str,
+    /// Shader member names with the offset of the matching Rust field. Members left out aren't
+    /// checked.
+    pub members: Vec<(&'static str, u32)>,
+}
+
+impl UniformBlock {
+    pub fn of<T>(group: u32, binding: u32) -> Self {
+        Self {
+            group,
+            binding,
+            size: std::mem::size_of::<T>() as u64,
+            type_name: std::any::type_name::<T>(),
+            members: Vec::new(),
+        }
+    }
+
+    pub fn with_members(mut self, members: Vec<(&'static str, u32)>) -> Self {
+        self.members = members;
+        self
+    }
+}
+
+/// Byte offset of `field` within `value`, for `UniformBlock::members`.
+pub(crate) fn member_offset<T, F>(value: &T, field: &F) -> u32 {
+    (field as *const F as usize - value as *const T as usize) as u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScalarKind {
+    Bool,
+    Float,
+    Sint,
+    Uint,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar {
+        kind: ScalarKind,
+        width: u32,
+    },
+    Vector {
+        kind: ScalarKind,
+        width: u32,
+        count: u32,
+    },
+    Matrix {
+        column: u32,
+        columns: u32,
+    },
+    Array {
+        length: u32,
+    },
+    Struct {
+        members: Vec<u32>,
+    },
+    Pointer {
+        pointee: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Decorations {
+    location: Option<u32>,
+    binding: Option<u32>,
+    descriptor_set: Option<u32>,
+    array_stride: Option<u32>,
+    built_in: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Variable {
+    id: u32,
+    ty: u32,
+    storage_class: u32,
+}
+
+#[derive(Debug, Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    decorations: HashMap<u32, Decorations>,
+    member_offsets: HashMap<(u32, u32), u32>,
+    member_matrix_strides: HashMap<(u32, u32), u32>,
+    member_built_ins: HashMap<u32, u32>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<Variable>,
+}
+
+fn parse_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|&word| (0..4).map(move |byte| (word >> (8 * byte)) as u8))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self, String> {
+        if words.len() < 5 || words[0] != SPIRV_MAGIC {
+            return Err("not SPIR-V".into());
+        }
+        let mut module = Module::default();
+        let mut index = 5;
+        while index < words.len() {
+            let count = (words[index] >> 16) as usize;
+            let opcode = words[index] & 0xffff;
+            if count == 0 || index + count > words.len() {
+                return Err(format!("truncated instruction at word {}", index));
+            }
+            module.parse_instruction(opcode, &words[index + 1..index + count]);
+            index += count;
+        }
+        Ok(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, operands: &[u32]) {
+        let operand = |index: usize| operands.get(index).copied();
+        match (opcode, operands) {
+            (OP_NAME, [target, name @ ..]) => {
+                self.names.insert(*target, parse_string(name));
+            }
+            (OP_MEMBER_NAME, [ty, member, name @ ..]) => {
+                self.member_names.insert((*ty, *member), parse_string(name));
+            }
+            (OP_DECORATE, [target, decoration, ..]) => {
+                let decorations = self.decorations.entry(*target).or_default();
+                match *decoration {
+                    DECORATION_LOCATION => decorations.location = operand(2),
+                    DECORATION_BINDING => decorations.binding = operand(2),
+                    DECORATION_DESCRIPTOR_SET => decorations.descriptor_set = operand(2),
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = operand(2),
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    _ => {}
+                }
+            }
+            (OP_MEMBER_DECORATE, [ty, member, decoration, value, ..]) => match *decoration {
+                DECORATION_OFFSET => {
+                    self.member_offsets.insert((*ty, *member), *value);
+                }
+                DECORATION_MATRIX_STRIDE => {
+                    self.member_matrix_strides.insert((*ty, *member), *value);
+                }
+                DECORATION_BUILT_IN => {
+                    self.member_built_ins.insert(*ty, *value);
+                }
+                _ => {}
+            },
+            (OP_TYPE_BOOL, [id]) => {
+                let kind = ScalarKind::Bool;
+                self.types.insert(*id, Type::Scalar { kind, width: 32 });
+            }
+            (OP_TYPE_INT, [id, width, signedness]) => {
+                let kind = if *signedness == 0 {
+                    ScalarKind::Uint
+                } else {
+                    ScalarKind::Sint
+                };
+                self.types.insert(
+                    *id,
+                    Type::Scalar {
+                        kind,
+                        width: *width,
+                    },
+                );
+            }
+            (OP_TYPE_FLOAT, [id, width]) => {
+                let kind = ScalarKind::Float;
+                self.types.insert(
+                    *id,
+                    Type::Scalar {
+                        kind,
+                        width: *width,
+                    },
+                );
+            }
+            (OP_TYPE_VECTOR, [id, component, count]) => {
+                if let Some(&Type::Scalar { kind, width }) = self.types.get(component) {
+                    let count = *count;
+                    self.types.insert(*id, Type::Vector { kind, width, count });
+                }
+            }
+            (OP_TYPE_MATRIX, [id, column, columns]) => {
+                let (column, columns) = (*column, *columns);
+                self.types.insert(*id, Type::Matrix { column, columns });
+            }
+            (OP_TYPE_ARRAY, [id, _element, length]) => {
+                if let Some(&length) = self.constants.get(length) {
+                    self.types.insert(*id, Type::Array { length });
+                }
+            }
+            (OP_TYPE_STRUCT, [id, members @ ..]) => {
+                let members = members.to_vec();
+                self.types.insert(*id, Type::Struct { members });
+            }
+            (OP_TYPE_POINTER, [id, _storage_class, pointee]) => {
+                let pointee = *pointee;
+                self.types.insert(*id, Type::Pointer { pointee });
+            }
+            (OP_CONSTANT, [_ty, id, value, ..]) => {
+                self.constants.insert(*id, *value);
+            }
+            (OP_VARIABLE, [ty, id, storage_class, ..]) => self.variables.push(Variable {
+                id: *id,
+                ty: *ty,
+                storage_class: *storage_class,
+            }),
+            _ => {}
+        }
+    }
+
+    fn decorations(&self, id: u32) -> Decorations {
+        self.decorations.get(&id).copied().unwrap_or_default()
+    }
+
+    fn name(&self, id: u32) -> &str {
+        self.names.get(&id).map_or("", String::as_str)
+    }
+
+    fn pointee(&self, variable: &Variable) -> Option<u32> {
+        match self.types.get(&variable.ty)? {
+            Type::Pointer { pointee } => Some(*pointee),
+            _ => None,
+        }
+    }
+
+    /// The interface blocks of built-ins like `gl_PerVertex` have no locations.
+    fn is_built_in(&self, variable: &Variable) -> bool {
+        self.decorations(variable.id).built_in
+            || self
+                .pointee(variable)
+                .map_or(false, |ty| self.member_built_ins.contains_key(&ty))
+    }
+
+    /// Size in bytes as laid out by the offset and stride decorations. Matrices are only sized
+    /// as struct members, where they carry their stride.
+    fn size(&self, ty: u32) -> Option<u32> {
+        match self.types.get(&ty)? {
+            Type::Scalar { width, .. } => Some(width / 8),
+            Type::Vector { width, count, .. } => Some(width / 8 * count),
+            Type::Array { length } => Some(self.decorations(ty).array_stride? * length),
+            Type::Struct { members } => {
+                members
+                    .iter()
+                    .enumerate()
+                    .try_fold(0, |size, (index, &member)| {
+                        let key = (ty, index as u32);
+                        let member_size = match self.types.get(&member)? {
+                            Type::Matrix { columns, .. } => {
+                                self.member_matrix_strides.get(&key)? * columns
+                            }
+                            _ => self.size(member)?,
+                        };
+                        Some(u32::max(size, self.member_offsets.get(&key)? + member_size))
+                    })
+            }
+            Type::Matrix { .. } | Type::Pointer { .. } => None,
+        }
+    }
+}
+
+/// The component type and count a vertex input reads from each of its locations.
+fn input_components(module: &Module, ty: u32) -> Option<(ScalarKind, u32, u32)> {
+    match *module.types.get(&ty)? {
+        Type::Scalar { kind, .. } => Some((kind, 1, 1)),
+        Type::Vector { kind, count, .. } => Some((kind, count, 1)),
+        Type::Matrix { column, columns } => {
+            let (kind, count, _) = input_components(module, column)?;
+            Some((kind, count, columns))
+        }
+        _ => None,
+    }
+}
+
+fn format_components(format: VertexFormat) -> (ScalarKind, u32) {
+    use ScalarKind::*;
+    use VertexFormat as F;
+    match format {
+        F::Uint => (Uint, 1),
+        F::Uchar2 | F::Ushort2 | F::Uint2 => (Uint, 2),
+        F::Uint3 => (Uint, 3),
+        F::Uchar4 | F::Ushort4 | F::Uint4 => (Uint, 4),
+        F::Int => (Sint, 1),
+        F::Char2 | F::Short2 | F::Int2 => (Sint, 2),
+        F::Int3 => (Sint, 3),
+        F::Char4 | F::Short4 | F::Int4 => (Sint, 4),
+        F::Float | F::Double => (Float, 1),
+        F::Uchar2Norm
+        | F::Char2Norm
+        | F::Ushort2Norm
+        | F::Short2Norm
+        | F::Half2
+        | F::Float2
+        | F::Double2 => (Float, 2),
+        F::Float3 | F::Double3 => (Float, 3),
+        F::Uchar4Norm
+        | F::Char4Norm
+        | F::Ushort4Norm
+        | F::Short4Norm
+        | F::Half4
+        | F::Float4
+        | F::Double4 => (Float, 4),
+    }
+}
+
+/// The GLSL name of a scalar or vector type.
+fn glsl_type(kind: ScalarKind, count: u32) -> String {
+    let (scalar, prefix) = match kind {
+        ScalarKind::Bool => ("bool", "b"),
+        ScalarKind::Float => ("float", ""),
+        ScalarKind::Sint => ("int", "i"),
+        ScalarKind::Uint => ("uint", "u"),
+    };
+    if count == 1 {
+        scalar.into()
+    } else {
+        format!("{}vec{}", prefix, count)
+    }
+}
+
+fn check_vertex_inputs(
+    module: &Module,
+    vertex_buffers: &[VertexBufferLayout],
+    errors: &mut Vec<String>,
+) {
+    let attributes: HashMap<u32, VertexFormat> = vertex_buffers
+        .iter()
+        .flat_map(|layout| layout.attributes)
+        .map(|attribute| (attribute.shader_location, attribute.format))
+        .collect();
+    for variable in &module.variables {
+        if variable.storage_class != STORAGE_CLASS_INPUT || module.is_built_in(variable) {
+            continue;
+        }
+        let name = module.name(variable.id);
+        let location = match module.decorations(variable.id).location {
+            Some(location) => location,
+            None => {
+                errors.push(format!("input `{}` has no location", name));
+                continue;
+            }
+        };
+        let (kind, count, locations) = match module
+            .pointee(variable)
+            .and_then(|ty| input_components(module, ty))
+        {
+            Some(components) => components,
+            None => {
+                errors.push(format!(
+                    "input `{}` at location {} can't be fed from a vertex buffer",
+                    name, location
+                ));
+                continue;
+            }
+        };
+        for location in location..location + locations {
+            match attributes.get(&location) {
+                None => errors.push(format!(
+                    "input `{}` at location {} has no vertex attribute",
+                    name, location
+                )),
+                Some(&format) if format_components(format) != (kind, count) => {
+                    errors.push(format!(
+                        "input `{}` at location {} is a {}, but its vertex attribute is {:?}",
+                        name,
+                        location,
+                        glsl_type(kind, count),
+                        format
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+fn check_uniform_blocks(
+    module: &Module,
+    uniform_blocks: &[UniformBlock],
+    errors: &mut Vec<String>,
+) {
+    for variable in &module.variables {
+        if variable.storage_class != STORAGE_CLASS_UNIFORM {
+            continue;
+        }
+        let decorations = module.decorations(variable.id);
+        let expected = uniform_blocks.iter().find(|block| {
+            Some(block.group) == decorations.descriptor_set
+                && Some(block.binding) == decorations.binding
+        });
+        let (expected, ty) = match (expected, module.pointee(variable)) {
+            (Some(expected), Some(ty)) => (expected, ty),
+            _ => continue,
+        };
+        let name = match module.name(ty) {
+            "" => module.name(variable.id),
+            name => name,
+        };
+        let size = match module.size(ty) {
+            Some(size) => size as u64,
+            None => {
+                log::warn!(
+                    "Uniform block `{}` at set {}, binding {} couldn't be sized.",
+                    name,
+                    expected.group,
+                    expected.binding
+                );
+                continue;
+            }
+        };
+        let members: Vec<(&str, Option<u32>)> = match module.types.get(&ty) {
+            Some(Type::Struct { members }) => (0..members.len() as u32)
+                .filter_map(|index| {
+                    let key = (ty, index);
+                    let name = module.member_names.get(&key)?;
+                    Some((name.as_str(), module.member_offsets.get(&key).copied()))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        // std140 rounds blocks up to 16 bytes, which the Rust struct may pad explicitly.
+        if expected.size < size || expected.size > (size + 15) / 16 * 16 {
+            let names: Vec<&str> = members.iter().map(|&(name, _)| name).collect();
+            errors.push(format!(
+                "uniform block `{}` at set {}, binding {} is {} bytes ({}), but `{}` is {} bytes",
+                name,
+                expected.group,
+                expected.binding,
+                size,
+                names.join(", "),
+                expected.type_name,
+                expected.size
+            ));
+        }
+        for &(member, expected_offset) in &expected.members {
+            match members.iter().find(|&&(name, _)| name == member) {
+                None => errors.push(format!(
+                    "uniform block `{}` at set {}, binding {} has no member `{}`",
+                    name, expected.group, expected.binding, member
+                )),
+                Some(&(_, Some(offset))) if offset != expected_offset => errors.push(format!(
+                    "`{}` of uniform block `{}` is at offset {}, but `{}` has it at {}",
+                    member, name, offset, expected.type_name, expected_offset
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// Checks the shader at `path` against the vertex buffers and uniform blocks of its pipeline.
+/// Uniform buffers without a matching `UniformBlock` aren't checked.
+pub(crate) fn check_shader(
+    path: &Path,
+    source: &wgpu::ShaderSource,
+    stage: wgpu::ShaderStage,
+    vertex_buffers: &[VertexBufferLayout],
+    uniform_blocks: &[UniformBlock],
+) -> Result<(), PipelineError> {
+    let words = match source {
+        wgpu::ShaderSource::SpirV(words) => words,
+        _ => return Ok(()),
+    };
+    let mut errors = Vec::new();
+    match Module::parse(words) {
+        Ok(module) => {
+            if stage == wgpu::ShaderStage::VERTEX {
+                check_vertex_inputs(&module, vertex_buffers, &mut errors);
+            }
+            check_uniform_blocks(&module, uniform_blocks, &mut errors);
+        }
+        Err(err) => errors.push(format!("failed to reflect: {}", err)),
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(PipelineError::Mismatch(
+        errors
+            .into_iter()
+            .map(|message| ShaderError {
+                path: path.to_owned(),
+                line: None,
+                message,
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SPIR-V compiled by glslang from the shaders of the same name in `assets/shaders`, with
+    /// no defines. After changing one of those shaders, regenerate its fixture with
+    /// `glslc -I assets/shaders assets/shaders/<name> -o src/reflect/fixtures/<name>.spv`.
+    fn fixture(name: &str) -> Module {
+        let bytes: &[u8] = match name {
+            "grass.vert" => include_bytes!("reflect/fixtures/grass.vert.spv"),
+            "fullscreen.vert" => include_bytes!("reflect/fixtures/fullscreen.vert.spv"),
+            "tonemap.frag" => include_bytes!("reflect/fixtures/tonemap.frag.spv"),
+            _ => unreachable!(),
+        };
+        let words: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        Module::parse(&words).unwrap()
+    }
+
+    fn attributes(formats: &[(u32, VertexFormat)]) -> Vec<wgpu::VertexAttribute> {
+        formats
+            .iter()
+            .map(|&(shader_location, format)| wgpu::VertexAttribute {
+                offset: 0,
+                shader_location,
+                format,
+            })
+            .collect()
+    }
+
+    fn check_grass_inputs(instance: &[(u32, VertexFormat)]) -> Vec<String> {
+        use VertexFormat::*;
+        let vertex = attributes(&[(0, Float3), (1, Float3), (2, Float2)]);
+        let instance = attributes(instance);
+        let layout = |attributes| VertexBufferLayout {
+            array_stride: 0,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes,
+        };
+        let layouts = [layout(&vertex), layout(&instance)];
+        let mut errors = Vec::new();
+        check_vertex_inputs(&fixture("grass.vert"), &layouts, &mut errors);
+        errors
+    }
+
+    fn check_uniforms(name: &str, uniform_blocks: &[UniformBlock]) -> Vec<String> {
+        let mut errors = Vec::new();
+        check_uniform_blocks(&fixture(name), uniform_blocks, &mut errors);
+        errors
+    }
+
+    #[test]
+    fn vertex_inputs_match_locations_and_formats() {
+        use VertexFormat::*;
+        let mut instance = vec![
+            (5, Float4),
+            (6, Float4),
+            (7, Float4),
+            (8, Float4),
+            (9, Uint),
+        ];
+        instance.extend(&[(10, Float3), (11, Float3), (12, Float), (13, Float3)]);
+        assert_eq!(check_grass_inputs(&instance), Vec::<String>::new());
+
+        instance[4] = (9, Float);
+        instance.pop();
+        assert_eq!(
+            check_grass_inputs(&instance),
+            [
+                "input `a_layer` at location 9 is a uint, but its vertex attribute is Float",
+                "input `a_tint` at location 13 has no vertex attribute",
+            ]
+        );
+    }
+
+    #[test]
+    fn built_in_inputs_need_no_vertex_buffer() {
+        let module = fixture("fullscreen.vert");
+        let inputs: Vec<&Variable> = module
+            .variables
+            .iter()
+            .filter(|variable| variable.storage_class == STORAGE_CLASS_INPUT)
+            .collect();
+        assert_eq!(inputs.len(), 1);
+        assert!(module.is_built_in(inputs[0]));
+
+        let mut errors = Vec::new();
+        check_vertex_inputs(&module, &[], &mut errors);
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn uniform_blocks_are_sized_by_member_offsets() {
+        let module = fixture("grass.vert");
+        let block = module
+            .variables
+            .iter()
+            .filter(|variable| variable.storage_class == STORAGE_CLASS_UNIFORM)
+            .find_map(|variable| module.pointee(variable))
+            .unwrap();
+        assert_eq!(module.name(block), "Uniforms");
+        assert_eq!(module.size(block), Some(256));
+        let member = |index| {
+            let key = (block, index);
+            (
+                module.member_names[&key].as_str(),
+                module.member_offsets[&key],
+            )
+        };
+        assert_eq!(member(4), ("u_season", 148));
+        // std140 aligns the matrix after the two floats to 16 bytes.
+        assert_eq!(member(5), ("u_inverse_view_proj", 160));
+        assert_eq!(member(7), ("u_fog_params", 240));
+    }
+
+    #[test]
+    fn uniform_blocks_match_struct_sizes() {
+        let tone_mapping = |size| UniformBlock {
+            group: 0,
+            binding: 2,
+            size,
+            type_name: "ToneMappingUniform",
+            members: Vec::new(),
+        };
+        assert_eq!(
+            check_uniforms("tonemap.frag", &[tone_mapping(16)]),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check_uniforms("tonemap.frag", &[tone_mapping(12)]),
+            ["uniform block `ToneMapping` at set 0, binding 2 is 16 bytes (u_exposure, u_gamma, \
+              u_operator, u_encode_srgb), but `ToneMappingUniform` is 12 bytes"]
+        );
+        // Blocks may be padded up to the next 16 bytes, and other bindings aren't checked.
+        let uniforms = |size| UniformBlock {
+            group: 0,
+            binding: 0,
+            size,
+            type_name: "Uniforms",
+            members: Vec::new(),
+        };
+        assert_eq!(
+            check_uniforms("grass.vert", &[uniforms(256)]),
+            Vec::<String>::new()
+        );
+        assert_eq!(check_uniforms("grass.vert", &[uniforms(272)]).len(), 1);
+        assert_eq!(
+            check_uniforms("grass.vert", &[tone_mapping(4)]),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn uniform_members_match_field_offsets() {
+        let tone_mapping = |members| UniformBlock::of::<[u32; 4]>(0, 2).with_members(members);
+        let members = vec![
+            ("u_exposure", 0),
+            ("u_gamma", 4),
+            ("u_operator", 8),
+            ("u_encode_srgb", 12),
+        ];
+        assert_eq!(
+            check_uniforms("tonemap.frag", &[tone_mapping(members)]),
+            Vec::<String>::new()
+        );
+        let members = vec![("u_gamma", 8), ("u_operator", 4), ("u_white_point", 12)];
+        assert_eq!(
+            check_uniforms("tonemap.frag", &[tone_mapping(members)]),
+            [
+                "`u_gamma` of uniform block `ToneMapping` is at offset 4, but `[u32; 4]` has it \
+                 at 8",
+                "`u_operator` of uniform block `ToneMapping` is at offset 8, but `[u32; 4]` has it \
+                 at 4",
+                "uniform block `ToneMapping` at set 0, binding 2 has no member `u_white_point`",
+            ]
+        );
+        // std140 aligns the matrix after the two floats to 16 bytes.
+        let uniforms = UniformBlock::of::<[u8; 256]>(0, 0)
+            .with_members(vec![("u_season", 148), ("u_inverse_view_proj", 152)]);
+        assert_eq!(
+            check_uniforms("grass.vert", &[uniforms]),
+            ["`u_inverse_view_proj` of uniform block `Uniforms` is at offset 160, but `[u8; 256]` \
+              has it at 152"]
+        );
+    }
+
+    #[test]
+    fn member_offsets_follow_the_struct_layout() {
+        #[repr(C)]
+        struct Block {
+            scale: f32,
+            _padding: [f32; 3],
+            color: [f32; 4],
+        }
+        let block = Block {
+            scale: 1.0,
+            _padding: [0.0; 3],
+            color: [0.0; 4],
+        };
+        assert_eq!(member_offset(&block, &block.scale), 0);
+        assert_eq!(member_offset(&block, &block.color), 16);
+    }
+
+    #[test]
+    fn only_spirv_is_reflected() {
+        assert!(Module::parse(&[0; 8]).is_err());
+        let source = wgpu::ShaderSource::Wgsl("".into());
+        let vertex = wgpu::ShaderStage::VERTEX;
+        assert!(check_shader(Path::new("post_copy.wgsl"), &source, vertex, &[], &[]).is_ok());
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+
+use crate::texture::Texture;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PassId(pub &'static str);
+
+impl PassId {
+    pub const MAIN: PassId = PassId("main");
+    pub const SHADOW: PassId = PassId("shadow");
+    /// Fills the scene color before `MAIN` draws on top of it.
+    pub const SKY: PassId = PassId("sky");
+    /// Blended geometry, drawn after `MAIN` into the same targets.
+    pub const TRANSPARENT: PassId = PassId("transparent");
+    /// Resolves `TargetId::HDR` into the swap chain.
+    pub const TONEMAP: PassId = PassId("tonemap");
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TargetId(pub &'static str);
+
+impl TargetId {
+    /// The current swap chain frame. It is owned by the swap chain, not the graph.
+    pub const SWAP_CHAIN: TargetId = TargetId("swap_chain");
+    /// Multisampled scene color, resolved into `HDR` at the end of `PassId::TRANSPARENT`.
+    pub const COLOR: TargetId = TargetId("color");
+    pub const HDR: TargetId = TargetId("hdr");
+    pub const DEPTH: TargetId = TargetId("depth");
+    pub const SHADOW_MAP: TargetId = TargetId("shadow_map");
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TargetSize {
+    /// Follows the swap chain and is recreated on resize.
+    SwapChain,
+    /// Follows the swap chain size divided by `divisor`.
+    SwapChainDivided {
+        divisor: u32,
+    },
+    Fixed {
+        width: u32,
+        height: u32,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct TargetDescriptor {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub size: TargetSize,
+}
+
+#[derive(Clone, Debug)]
+pub struct ColorAttachment {
+    pub target: TargetId,
+    pub resolve_target: Option<TargetId>,
+    pub load: wgpu::LoadOp<wgpu::Color>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DepthAttachment {
+    pub target: TargetId,
+    pub load: wgpu::LoadOp<f32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PassDescriptor {
+    pub id: PassId,
+    pub color_attachments: Vec<ColorAttachment>,
+    pub depth_attachment: Option<DepthAttachment>,
+    /// Passes that have to be executed before this one. Passes that are not in the graph are
+    /// ignored.
+    pub dependencies: Vec<PassId>,
+}
+
+/// Attachment formats of a pass. Pipelines and render bundles recorded into the pass must match.
+#[derive(Clone, Debug)]
+pub struct PassFormats {
+    pub color_formats: Vec<wgpu::TextureFormat>,
+    pub depth_format: Option<wgpu::TextureFormat>,
+    pub sample_count: u32,
+}
+
+impl PassFormats {
+    pub fn create_bundle_encoder<'a>(
+        &self,
+        device: &'a wgpu::Device,
+        label: Option<&str>,
+    ) -> wgpu::RenderBundleEncoder<'a> {
+        device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+            label,
+            color_formats: &self.color_formats,
+            depth_stencil_format: self.depth_format,
+            sample_count: self.sample_count,
+        })
+    }
+}
+
+struct Target {
+    desc: TargetDescriptor,
+    texture: Texture,
+}
+
+pub struct RenderGraph {
+    swap_chain_format: wgpu::TextureFormat,
+    swap_chain_size: (u32, u32),
+    targets: HashMap<TargetId, Target>,
+    passes: Vec<PassDescriptor>,
+    order: Vec<usize>,
+}
+
+fn create_target(
+    device: &wgpu::Device,
+    id: TargetId,
+    desc: &TargetDescriptor,
+    swap_chain_size: (u32, u32),
+) -> Texture {
+    let (width, height) = match desc.size {
+        TargetSize::SwapChain => swap_chain_size,
+        TargetSize::SwapChainDivided { divisor } => {
+            let divisor = divisor.max(1);
+            (
+                (swap_chain_size.0 / divisor).max(1),
+                (swap_chain_size.1 / divisor).max(1),
+            )
+        }
+        TargetSize::Fixed { width, height } => (width, height),
+    };
+    Texture::create_render_target(
+        device,
+        id.0,
+        wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+        desc.format,
+        desc.sample_count,
+    )
+}
+
+impl RenderGraph {
+    pub fn new(sc_desc: &wgpu::SwapChainDescriptor) -> Self {
+        Self {
+            swap_chain_format: sc_desc.format,
+            swap_chain_size: (sc_desc.width, sc_desc.height),
+            targets: HashMap::new(),
+            passes: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Adds a render target, replacing any target with the same id.
+    pub fn add_target(&mut self, device: &wgpu::Device, id: TargetId, desc: TargetDescriptor) {
+        if id == TargetId::SWAP_CHAIN {
+            log::error!("Render target {:?} is reserved.", id);
+            return;
+        }
+        let texture = create_target(device, id, &desc, self.swap_chain_size);
+        self.targets.insert(id, Target { desc, texture });
+    }
+
+    /// Adds a pass, replacing any pass with the same id, and reorders the graph.
+    pub fn add_pass(&mut self, desc: PassDescriptor) {
+        let attachments = desc
+            .color_attachments
+            .iter()
+            .flat_map(|attachment| {
+                std::iter::once(attachment.target).chain(attachment.resolve_target)
+            })
+            .chain(
+                desc.depth_attachment
+                    .iter()
+                    .map(|attachment| attachment.target),
+            );
+        for target in attachments {
+            if target != TargetId::SWAP_CHAIN && !self.targets.contains_key(&target) {
+                log::error!("Pass {:?} uses unknown target {:?}.", desc.id, target);
+                return;
+            }
+        }
+
+        match self.passes.iter_mut().find(|pass| pass.id == desc.id) {
+            Some(pass) => *pass = desc,
+            None => self.passes.push(desc),
+        }
+        self.order = self.sort_passes();
+    }
+
+    /// Removes a pass and reorders the graph. Passes depending on it no longer wait for it.
+    pub fn remove_pass(&mut self, id: PassId) {
+        self.passes.retain(|pass| pass.id != id);
+        self.order = self.sort_passes();
+    }
+
+    pub fn target(&self, id: TargetId) -> Option<&Texture> {
+        self.targets.get(&id).map(|target| &target.texture)
+    }
+
+    pub fn target_format(&self, id: TargetId) -> Option<wgpu::TextureFormat> {
+        if id == TargetId::SWAP_CHAIN {
+            Some(self.swap_chain_format)
+        } else {
+            self.targets.get(&id).map(|target| target.desc.format)
+        }
+    }
+
+    fn target_sample_count(&self, id: TargetId) -> u32 {
+        self.targets
+            .get(&id)
+            .map_or(1, |target| target.desc.sample_count)
+    }
+
+    pub fn pass(&self, id: PassId) -> Option<&PassDescriptor> {
+        self.passes.iter().find(|pass| pass.id == id)
+    }
+
+    pub fn pass_formats(&self, id: PassId) -> Option<PassFormats> {
+        let pass = self.pass(id)?;
+        let color_formats = pass
+            .color_attachments
+            .iter()
+            .map(|attachment| self.target_format(attachment.target))
+            .collect::<Option<Vec<_>>>()?;
+        let depth_format = match pass.depth_attachment {
+            Some(ref attachment) => Some(self.target_format(attachment.target)?),
+            None => None,
+        };
+        let sample_count = pass
+            .color_attachments
+            .iter()
+            .map(|attachment| attachment.target)
+            .chain(
+                pass.depth_attachment
+                    .iter()
+                    .map(|attachment| attachment.target),
+            )
+            .map(|target| self.target_sample_count(target))
+            .next()
+            .unwrap_or(1);
+
+        Some(PassFormats {
+            color_formats,
+            depth_format,
+            sample_count,
+        })
+    }
+
+    /// Passes in execution order.
+    pub fn ordered_passes(&self) -> impl Iterator<Item = &PassDescriptor> {
+        self.order.iter().map(move |&index| &self.passes[index])
+    }
+
+    /// Recreates every target that follows the swap chain size.
+    pub fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        self.swap_chain_format = sc_desc.format;
+        self.swap_chain_size = (sc_desc.width, sc_desc.height);
+        for (&id, target) in self.targets.iter_mut() {
+            if let TargetSize::SwapChain | TargetSize::SwapChainDivided { .. } = target.desc.size {
+                target.texture = create_target(device, id, &target.desc, self.swap_chain_size);
+            }
+        }
+    }
+
+    // Kahn's algorithm, ties broken by registration order.
+    fn sort_passes(&self) -> Vec<usize> {
+        let mut remaining: Vec<usize> = (0..self.passes.len()).collect();
+        let mut order = Vec::with_capacity(self.passes.len());
+
+        while !remaining.is_empty() {
+            let ready = remaining.iter().position(|&index| {
+                self.passes[index].dependencies.iter().all(|dependency| {
+                    !remaining
+                        .iter()
+                        .any(|&other| self.passes[other].id == *dependency)
+                })
+            });
+
+            match ready {
+                Some(position) => order.push(remaining.remove(position)),
+                None => {
+                    log::error!(
+                        "Render graph has a dependency cycle, skipping passes {:?}.",
+                        remaining
+                            .iter()
+                            .map(|&index| self.passes[index].id)
+                            .collect::<Vec<_>>()
+                    );
+                    break;
+                }
+            }
+        }
+
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> RenderGraph {
+        RenderGraph::new(&wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: 64,
+            height: 64,
+            present_mode: wgpu::PresentMode::Fifo,
+        })
+    }
+
+    fn pass(id: &'static str, dependencies: &[&'static str]) -> PassDescriptor {
+        PassDescriptor {
+            id: PassId(id),
+            color_attachments: vec![ColorAttachment {
+                target: TargetId::SWAP_CHAIN,
+                resolve_target: None,
+                load: wgpu::LoadOp::Load,
+            }],
+            depth_attachment: None,
+            dependencies: dependencies.iter().map(|&id| PassId(id)).collect(),
+        }
+    }
+
+    fn order(graph: &RenderGraph) -> Vec<&'static str> {
+        graph.ordered_passes().map(|pass| pass.id.0).collect()
+    }
+
+    #[test]
+    fn passes_run_after_their_dependencies() {
+        let mut graph = graph();
+        graph.add_pass(pass("tonemap", &["main", "transparent"]));
+        graph.add_pass(pass("transparent", &["main"]));
+        graph.add_pass(pass("main", &["shadow", "sky"]));
+        graph.add_pass(pass("sky", &[]));
+        graph.add_pass(pass("shadow", &[]));
+        assert_eq!(
+            order(&graph),
+            ["sky", "shadow", "main", "transparent", "tonemap"]
+        );
+    }
+
+    #[test]
+    fn independent_passes_keep_registration_order() {
+        let mut graph = graph();
+        graph.add_pass(pass("b", &[]));
+        graph.add_pass(pass("a", &[]));
+        graph.add_pass(pass("c", &["missing"]));
+        assert_eq!(order(&graph), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn replacing_and_removing_passes_reorders() {
+        let mut graph = graph();
+        graph.add_pass(pass("a", &[]));
+        graph.add_pass(pass("b", &[]));
+        graph.add_pass(pass("a", &["b"]));
+        assert_eq!(order(&graph), ["b", "a"]);
+        graph.remove_pass(PassId("b"));
+        assert_eq!(order(&graph), ["a"]);
+    }
+
+    #[test]
+    fn cycles_are_skipped() {
+        let mut graph = graph();
+        graph.add_pass(pass("first", &[]));
+        graph.add_pass(pass("a", &["c"]));
+        graph.add_pass(pass("b", &["a"]));
+        graph.add_pass(pass("c", &["b"]));
+        graph.add_pass(pass("last", &["first"]));
+        assert_eq!(order(&graph), ["first", "last"]);
+
+        // Breaking the cycle brings its passes back.
+        graph.add_pass(pass("a", &["first"]));
+        assert_eq!(order(&graph), ["first", "a", "b", "c", "last"]);
+    }
+
+    #[test]
+    fn passes_with_unknown_targets_are_rejected() {
+        let mut graph = graph();
+        let mut desc = pass("main", &[]);
+        desc.depth_attachment = Some(DepthAttachment {
+            target: TargetId::DEPTH,
+            load: wgpu::LoadOp::Clear(1.0),
+        });
+        graph.add_pass(desc);
+        assert!(graph.pass(PassId("main")).is_none());
+    }
+}
@@ -0,0 +1,37 @@
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::{lighting::Lights, post::PostEffect, sky::Fog, tonemap::ToneMapping};
+
+/// Scene settings read from a RON file. Missing fields keep their defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    pub lights: Lights,
+    pub fog: Fog,
+    pub tone_mapping: ToneMapping,
+    pub post_effects: Vec<PostEffect>,
+    /// Blends the grass from its own colors at 0 through yellow at 0.5 to brown at 1.
+    pub season: f32,
+}
+
+impl Scene {
+    pub const PATH: &'static str = "assets/scene.ron";
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let text = match std::fs::read_to_string(path.as_ref()) {
+            Ok(text) => text,
+            Err(err) => {
+                log::error!("Failed to read scene {:?} {:?}", path.as_ref(), err);
+                return None;
+            }
+        };
+        match ron::from_str(&text) {
+            Ok(scene) => Some(scene),
+            Err(err) => {
+                log::error!("Failed to parse scene {:?} {}", path.as_ref(), err);
+                None
+            }
+        }
+    }
+}
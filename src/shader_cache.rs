@@ -0,0 +1,199 @@
+//! Compiled SPIR-V stored on disk, keyed by everything that goes into compiling a shader.
+//!
+//! Shipping the cache directory lets builds without the `shader-compiler` feature load their
+//! shaders without shaderc.
+
+use std::{
+    hash::Hasher,
+    path::{Path, PathBuf},
+};
+
+use crate::pipeline::{shader_includes, ShaderDefines};
+
+/// Directory of the cached SPIR-V, relative to the working directory like the assets.
+pub const SHADER_CACHE_DIR: &str = "shader_cache";
+
+/// Part of every key, bump it along with the shaderc version in Cargo.toml so stale SPIR-V is
+/// left behind.
+const COMPILER_VERSION: &str = "shaderc-0.7";
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is fixed, so keys stay valid across
+/// toolchains and between the build that fills the cache and the one that ships it.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Length prefixed, so consecutive strings can't run into each other.
+fn write_str(hasher: &mut Fnv1a, text: &str) {
+    hasher.write(&(text.len() as u64).to_le_bytes());
+    hasher.write(text.as_bytes());
+}
+
+/// Identifies a cache entry. `permutation` hashes what picks the shader, its stage and defines,
+/// and `contents` what it compiles from, so an entry whose contents went stale can be found by
+/// the permutation it shares with its replacement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Key {
+    permutation: u64,
+    contents: u64,
+}
+
+/// Key of the shader at `path`, from its source, includes, defines, stage and the compiler.
+pub(crate) fn key(
+    path: &Path,
+    source: &str,
+    stage: wgpu::ShaderStage,
+    defines: &ShaderDefines,
+) -> Key {
+    let mut hasher = Fnv1a::default();
+    hasher.write(&stage.bits().to_le_bytes());
+    for (name, value) in defines.iter() {
+        write_str(&mut hasher, name);
+        match value {
+            Some(value) => write_str(&mut hasher, value),
+            None => hasher.write(&[0xff]),
+        }
+    }
+    let permutation = hasher.finish();
+
+    let mut hasher = Fnv1a::default();
+    write_str(&mut hasher, COMPILER_VERSION);
+    write_str(&mut hasher, source);
+    // Only the contents count, as path separators differ between platforms. A missing include
+    // hashes as empty and compiling reports it.
+    for include in shader_includes(path) {
+        write_str(
+            &mut hasher,
+            &std::fs::read_to_string(&include).unwrap_or_default(),
+        );
+    }
+    Key {
+        permutation,
+        contents: hasher.finish(),
+    }
+}
+
+/// File name prefix shared by every version of a permutation.
+fn permutation_prefix(path: &Path, key: Key) -> String {
+    let name = path
+        .file_name()
+        .map_or_else(|| "shader".into(), |name| name.to_string_lossy());
+    format!("{}-{:016x}-", name, key.permutation)
+}
+
+fn cache_path(dir: &Path, path: &Path, key: Key) -> PathBuf {
+    let prefix = permutation_prefix(path, key);
+    dir.join(format!("{}{:016x}.spv", prefix, key.contents))
+}
+
+/// The cached SPIR-V of the shader at `path`, if there is a valid entry for `key`.
+pub(crate) fn load(path: &Path, key: Key) -> Option<Vec<u32>> {
+    let bytes = std::fs::read(cache_path(Path::new(SHADER_CACHE_DIR), path, key)).ok()?;
+    if bytes.len() % 4 != 0 {
+        return None;
+    }
+    let words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    if words.first() != Some(&SPIRV_MAGIC) {
+        return None;
+    }
+    Some(words)
+}
+
+/// Writes the entry through a temporary file, so a crash can't leave a truncated one behind,
+/// and removes the entries of the same permutation it replaces.
+#[cfg(feature = "shader-compiler")]
+pub(crate) fn store(path: &Path, key: Key, words: &[u32]) {
+    store_in(Path::new(SHADER_CACHE_DIR), path, key, words);
+}
+
+#[cfg(feature = "shader-compiler")]
+fn store_in(dir: &Path, path: &Path, key: Key, words: &[u32]) {
+    let cache_path = cache_path(dir, path, key);
+    let temp_path = cache_path.with_extension("tmp");
+    let mut bytes = Vec::with_capacity(words.len() * 4);
+    for word in words {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    let result = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&temp_path, &bytes))
+        .and_then(|_| std::fs::rename(&temp_path, &cache_path));
+    if let Err(err) = result {
+        log::warn!("Failed to cache shader {:?}: {}", cache_path, err);
+        return;
+    }
+
+    let prefix = permutation_prefix(path, key);
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let stale = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && name.ends_with(".spv") && stale != cache_path {
+            if let Err(err) = std::fs::remove_file(&stale) {
+                log::warn!("Failed to remove stale shader {:?}: {}", stale, err);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "shader-compiler"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storing_replaces_stale_entries_of_the_permutation() {
+        let dir = std::env::temp_dir().join(format!("shader_cache_test_{}", std::process::id()));
+        let path = Path::new("grass.vert");
+        let entries = || {
+            let mut names: Vec<String> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        let key = |permutation, contents| Key {
+            permutation,
+            contents,
+        };
+        let words = [SPIRV_MAGIC, 1];
+
+        store_in(&dir, path, key(1, 1), &words);
+        store_in(&dir, path, key(2, 1), &words);
+        store_in(&dir, Path::new("sky.frag"), key(1, 1), &words);
+        store_in(&dir, path, key(1, 2), &words);
+        assert_eq!(
+            entries(),
+            [
+                "grass.vert-0000000000000001-0000000000000002.spv",
+                "grass.vert-0000000000000002-0000000000000001.spv",
+                "sky.frag-0000000000000001-0000000000000001.spv",
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+use ultraviolet::{projection::rh_yup::orthographic_wgpu_dx, Mat4, Vec3};
+
+use crate::render_graph::{
+    DepthAttachment, PassDescriptor, PassId, RenderGraph, TargetDescriptor, TargetId, TargetSize,
+};
+use crate::texture::Texture;
+
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+/// Orthographic view-projection of a directional light that covers the sphere at `center`.
+/// `direction` points towards the light.
+pub fn directional_light_view_proj(direction: Vec3, center: Vec3, radius: f32) -> Mat4 {
+    let direction = direction.normalized();
+    let eye = center + direction * radius * 2.0;
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::unit_z()
+    } else {
+        Vec3::unit_y()
+    };
+    orthographic_wgpu_dx(-radius, radius, -radius, radius, radius, radius * 3.0)
+        * Mat4::look_at(eye, center, up)
+}
+
+/// Adds the shadow map target and the depth-only pass that renders it.
+pub fn add_shadow_pass(device: &wgpu::Device, render_graph: &mut RenderGraph) {
+    render_graph.add_target(
+        device,
+        TargetId::SHADOW_MAP,
+        TargetDescriptor {
+            format: Texture::DEPTH_FORMAT,
+            sample_count: 1,
+            size: TargetSize::Fixed {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+            },
+        },
+    );
+
+    render_graph.add_pass(PassDescriptor {
+        id: PassId::SHADOW,
+        color_attachments: Vec::new(),
+        depth_attachment: Some(DepthAttachment {
+            target: TargetId::SHADOW_MAP,
+            load: wgpu::LoadOp::Clear(1.0),
+        }),
+        dependencies: Vec::new(),
+    });
+}
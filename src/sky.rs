@@ -0,0 +1,89 @@
+use serde::Deserialize;
+use std::{collections::HashSet, path::PathBuf};
+use ultraviolet::Vec3;
+
+use crate::{pipeline::PipelineBuilder, Dependencies, PassId, PipelineHandler, State};
+
+/// Exponential height fog, applied by the grass and ground shaders.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct Fog {
+    pub color: Vec3,
+    /// Density at `base_height`.
+    pub density: f32,
+    /// How quickly the density decreases above `base_height`.
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// Distance from the camera without fog.
+    pub start_distance: f32,
+    pub max_opacity: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            color: Vec3::new(0.6, 0.72, 0.9),
+            density: 0.1,
+            height_falloff: 2.0,
+            base_height: 0.0,
+            start_distance: 1.0,
+            max_opacity: 1.0,
+        }
+    }
+}
+
+fn create_bundle(state: &State) -> Option<wgpu::RenderBundle> {
+    let formats = state.pass_formats(PassId::SKY)?;
+    let pipeline = PipelineBuilder::new("assets/shaders/fullscreen.vert")
+        .with_fragment_shader("assets/shaders/sky.frag")
+        .with_formats(&formats)
+        .with_bind_group_layouts(&[state.pass_bind_group_layout(PassId::SKY)])
+        .with_uniform_blocks(&state.pass_uniform_blocks(PassId::SKY))
+        .build(&state.device);
+    let pipeline = state.report_errors(pipeline)?;
+
+    let mut encoder = formats.create_bundle_encoder(&state.device, None);
+
+    encoder.set_pipeline(&pipeline);
+    encoder.set_bind_group(0, state.pass_bind_group(PassId::SKY), &[]);
+    encoder.draw(0..3, 0..1);
+
+    Some(encoder.finish(&wgpu::RenderBundleDescriptor { label: Some("sky") }))
+}
+
+/// Draws a procedural sky lit by the sun of `State::lights` behind the scene.
+pub struct SkyPipeline {
+    render_bundle: Option<wgpu::RenderBundle>,
+}
+
+impl SkyPipeline {
+    pub fn create(state: &State) -> Self {
+        Self {
+            render_bundle: create_bundle(state),
+        }
+    }
+}
+
+impl PipelineHandler for SkyPipeline {
+    fn passes(&self) -> &[PassId] {
+        &[PassId::SKY]
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        Dependencies::default()
+            .with_shader("assets/shaders/fullscreen.vert")
+            .with_shader("assets/shaders/sky.frag")
+    }
+
+    fn files_changed(&mut self, state: &mut State, _changed: &HashSet<PathBuf>) {
+        let bundle = create_bundle(state);
+        if bundle.is_some() {
+            self.render_bundle = bundle;
+            log::info!("Sky bundle reloaded.");
+        }
+    }
+
+    fn render_bundle(&mut self, _state: &State, _pass: PassId) -> Option<&wgpu::RenderBundle> {
+        self.render_bundle.as_ref()
+    }
+}
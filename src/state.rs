@@ -0,0 +1,848 @@
+use crate::{
+    assets::{AssetOwner, AssetRegistry, Dependencies},
+    input,
+    lighting::{Lights, LightsUniform},
+    material,
+    overlay::{ErrorOverlay, ShaderErrors},
+    perspective_camera::PerspectiveCamera,
+    pipeline::{self, PipelineError, ShaderDefines, ShaderError},
+    post::PostEffect,
+    reflect::{self, UniformBlock},
+    render_graph::{
+        ColorAttachment, DepthAttachment, PassDescriptor, PassFormats, PassId, RenderGraph,
+        TargetDescriptor, TargetId, TargetSize,
+    },
+    scene::Scene,
+    shadow,
+    sky::Fog,
+    texture::Texture,
+    tonemap::ToneMapping,
+    PipelineHandler,
+};
+use std::{cell::RefCell, collections::HashSet, iter, path::PathBuf, time::Duration};
+use ultraviolet::{Mat4, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+use winit::{event::*, window::Window};
+
+const BACKGROUND_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.2,
+    g: 0.5,
+    b: 1.0,
+    a: 1.0,
+};
+
+/// Device features that are enabled when the adapter supports them. Compressed textures fall
+/// back to CPU decoding without `TEXTURE_COMPRESSION_BC`.
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC;
+
+pub struct State {
+    keyboard_input: input::Input<VirtualKeyCode>,
+
+    surface: wgpu::Surface,
+    pub(crate) device: wgpu::Device,
+    pub(crate) queue: wgpu::Queue,
+    pub(crate) sc_desc: wgpu::SwapChainDescriptor,
+    swap_chain: wgpu::SwapChain,
+    size: winit::dpi::PhysicalSize<u32>,
+
+    perspective_camera: PerspectiveCamera,
+    pub(crate) render_graph: RenderGraph,
+
+    lights: Lights,
+    fog: Fog,
+    tone_mapping: ToneMapping,
+    pub(crate) tone_mapping_buffer: wgpu::Buffer,
+    post_effects: Vec<PostEffect>,
+    season: f32,
+    shader_defines: ShaderDefines,
+    assets: AssetRegistry,
+    shader_errors: RefCell<ShaderErrors>,
+    error_overlay: ErrorOverlay,
+
+    pub(crate) uniform_bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    lights_buffer: wgpu::Buffer,
+    pub(crate) uniform_bind_group: wgpu::BindGroup,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+
+    shadow_pass_bind_group_layout: wgpu::BindGroupLayout,
+    shadow_uniform_buffer: wgpu::Buffer,
+    shadow_pass_bind_group: wgpu::BindGroup,
+}
+
+#[repr(C)]
+// This is so we can store this in a buffer
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Uniforms {
+    view_proj: Mat4,
+    light_view_proj: Mat4,
+    view_position: Vec4,
+    time: f32,
+    season: f32,
+    _padding: [f32; 2],
+    inverse_view_proj: Mat4,
+    /// rgb: color, w: density.
+    fog_color: Vec4,
+    /// x: height falloff, y: base height, z: max opacity, w: start distance.
+    fog_params: Vec4,
+}
+
+impl Uniforms {
+    fn new(
+        camera: &PerspectiveCamera,
+        light_view_proj: Mat4,
+        fog: &Fog,
+        season: f32,
+        time: f32,
+    ) -> Self {
+        let view_proj = camera.compute_matrix();
+        Self {
+            view_proj,
+            light_view_proj,
+            view_position: camera.eye.into_homogeneous_vector(),
+            time,
+            season,
+            _padding: [0.0; 2],
+            inverse_view_proj: view_proj.inversed(),
+            fog_color: Vec4::new(fog.color.x, fog.color.y, fog.color.z, fog.density),
+            fog_params: Vec4::new(
+                fog.height_falloff,
+                fog.base_height,
+                fog.max_opacity,
+                fog.start_distance,
+            ),
+        }
+    }
+
+    /// Offsets of the `Uniforms` block members in uniforms.glsl.
+    fn members() -> Vec<(&'static str, u32)> {
+        let uniforms: Self = bytemuck::Zeroable::zeroed();
+        vec![
+            (
+                "u_view_proj",
+                reflect::member_offset(&uniforms, &uniforms.view_proj),
+            ),
+            (
+                "u_light_view_proj",
+                reflect::member_offset(&uniforms, &uniforms.light_view_proj),
+            ),
+            (
+                "u_view_position",
+                reflect::member_offset(&uniforms, &uniforms.view_position),
+            ),
+            ("u_time", reflect::member_offset(&uniforms, &uniforms.time)),
+            (
+                "u_season",
+                reflect::member_offset(&uniforms, &uniforms.season),
+            ),
+            (
+                "u_inverse_view_proj",
+                reflect::member_offset(&uniforms, &uniforms.inverse_view_proj),
+            ),
+            (
+                "u_fog_color",
+                reflect::member_offset(&uniforms, &uniforms.fog_color),
+            ),
+            (
+                "u_fog_params",
+                reflect::member_offset(&uniforms, &uniforms.fog_params),
+            ),
+        ]
+    }
+}
+
+// Bounding sphere of the scene that the shadow map covers.
+const SHADOW_CENTER: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+const SHADOW_RADIUS: f32 = 1.5;
+
+fn create_uniform_buffer_layout_entry() -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+impl State {
+    pub async fn new(window: &Window) -> Self {
+        let size = window.inner_size();
+        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: Some(&surface),
+            })
+            .await
+            .unwrap();
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: adapter.features() & OPTIONAL_FEATURES,
+                    limits: wgpu::Limits::default(),
+                },
+                None, // Trace path
+            )
+            .await
+            .unwrap();
+        pipeline::capture_validation_errors(&device);
+
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format: adapter.get_swap_chain_preferred_format(&surface),
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+
+        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+
+        let render_graph = create_default_render_graph(&device, &sc_desc);
+
+        let perspective_camera = PerspectiveCamera {
+            eye: (0.0, 1.0, 4.0).into(),
+            at: (0.0, 0.0, 0.0).into(),
+            up: Vec3::unit_y(),
+            vertical_fov: std::f32::consts::PI / 4.0,
+            aspect_ratio: sc_desc.width as f32 / sc_desc.height as f32,
+            z_near: 0.1,
+            z_far: 100.0,
+        };
+
+        let scene = Scene::load(Scene::PATH).unwrap_or_default();
+        let lights = scene.lights;
+        let fog = scene.fog;
+        let tone_mapping = scene.tone_mapping;
+        let post_effects = scene.post_effects;
+        let season = scene.season.clamp(0.0, 1.0);
+        let mut assets = AssetRegistry::default();
+        assets.register(
+            AssetOwner::Scene,
+            Dependencies::default().with_file(Scene::PATH),
+        );
+        let light_view_proj =
+            shadow::directional_light_view_proj(lights.sun.direction, SHADOW_CENTER, SHADOW_RADIUS);
+        let uniforms = Uniforms::new(&perspective_camera, light_view_proj, &fog, season, 0.0);
+
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    create_uniform_buffer_layout_entry(),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: true,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("uniform_bind_group_layout"),
+            });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform Buffer"),
+            contents: bytemuck::bytes_of(&uniforms),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let lights_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lights Buffer"),
+            contents: bytemuck::bytes_of(&lights.to_uniform()),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let tone_mapping_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tone Mapping Buffer"),
+            contents: bytemuck::bytes_of(&tone_mapping.to_uniform(sc_desc.format)),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let shadow_map = render_graph
+            .target(TargetId::SHADOW_MAP)
+            .expect("Default render graph has a shadow map.");
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("uniform_bind_group"),
+        });
+
+        let material_bind_group_layout = material::create_bind_group_layout(&device);
+
+        // The shadow pass can't bind the shadow map it renders to, so it gets its own bind group
+        // whose uniforms view the scene from the light.
+        let shadow_pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[create_uniform_buffer_layout_entry()],
+                label: Some("shadow_pass_bind_group_layout"),
+            });
+
+        let shadow_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            contents: bytemuck::bytes_of(&Uniforms {
+                view_proj: light_view_proj,
+                ..uniforms
+            }),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let shadow_pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &shadow_pass_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: shadow_uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("shadow_pass_bind_group"),
+        });
+
+        Self {
+            keyboard_input: Default::default(),
+            surface,
+            device,
+            queue,
+            sc_desc,
+            swap_chain,
+            size,
+            perspective_camera,
+            render_graph,
+            lights,
+            fog,
+            tone_mapping,
+            tone_mapping_buffer,
+            post_effects,
+            season,
+            shader_defines: ShaderDefines::default()
+                .with("WIND_ENABLED")
+                .with("SHADOWS"),
+            assets,
+            shader_errors: RefCell::default(),
+            error_overlay: ErrorOverlay::default(),
+
+            uniform_bind_group_layout,
+            uniform_buffer,
+            lights_buffer,
+            uniform_bind_group,
+            material_bind_group_layout,
+
+            shadow_pass_bind_group_layout,
+            shadow_uniform_buffer,
+            shadow_pass_bind_group,
+        }
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn sc_desc(&self) -> &wgpu::SwapChainDescriptor {
+        &self.sc_desc
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Position of the camera, for sorting blended geometry.
+    pub fn view_position(&self) -> Vec3 {
+        self.perspective_camera.eye
+    }
+
+    /// Layout of bind group 0 in the main pass: uniforms, the shadow map and the lights.
+    pub fn uniform_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.uniform_bind_group_layout
+    }
+
+    pub fn uniform_bind_group(&self) -> &wgpu::BindGroup {
+        &self.uniform_bind_group
+    }
+
+    /// Layout of bind group 1 in the main pass, see `Material`.
+    pub fn material_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.material_bind_group_layout
+    }
+
+    /// Layout of bind group 0 for pipelines recorded into `pass`.
+    pub fn pass_bind_group_layout(&self, pass: PassId) -> &wgpu::BindGroupLayout {
+        if pass == PassId::SHADOW {
+            &self.shadow_pass_bind_group_layout
+        } else {
+            &self.uniform_bind_group_layout
+        }
+    }
+
+    /// Uniform buffers of `pass_bind_group_layout`, for checking shaders against them.
+    pub fn pass_uniform_blocks(&self, pass: PassId) -> Vec<UniformBlock> {
+        let uniforms = UniformBlock::of::<Uniforms>(0, 0).with_members(Uniforms::members());
+        if pass == PassId::SHADOW {
+            vec![uniforms]
+        } else {
+            let lights = UniformBlock::of::<LightsUniform>(0, 3);
+            vec![uniforms, lights.with_members(LightsUniform::members())]
+        }
+    }
+
+    pub fn pass_bind_group(&self, pass: PassId) -> &wgpu::BindGroup {
+        if pass == PassId::SHADOW {
+            &self.shadow_pass_bind_group
+        } else {
+            &self.uniform_bind_group
+        }
+    }
+
+    /// The sun in `lights` is the light that casts shadows.
+    pub fn lights(&self) -> &Lights {
+        &self.lights
+    }
+
+    pub fn set_lights(&mut self, lights: Lights) {
+        self.queue.write_buffer(
+            &self.lights_buffer,
+            0,
+            bytemuck::bytes_of(&lights.to_uniform()),
+        );
+        self.lights = lights;
+    }
+
+    pub fn fog(&self) -> &Fog {
+        &self.fog
+    }
+
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.fog = fog;
+    }
+
+    pub fn tone_mapping(&self) -> &ToneMapping {
+        &self.tone_mapping
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.queue.write_buffer(
+            &self.tone_mapping_buffer,
+            0,
+            bytemuck::bytes_of(&tone_mapping.to_uniform(self.sc_desc.format)),
+        );
+        self.tone_mapping = tone_mapping;
+    }
+
+    /// The post effect stack, applied in order by `PostProcessPipeline`.
+    pub fn post_effects(&self) -> &[PostEffect] {
+        &self.post_effects
+    }
+
+    pub fn set_post_effects(&mut self, post_effects: Vec<PostEffect>) {
+        self.post_effects = post_effects;
+    }
+
+    /// 0 leaves the grass at its own colors, 0.5 is yellow and 1 is brown.
+    pub fn season(&self) -> f32 {
+        self.season
+    }
+
+    pub fn set_season(&mut self, season: f32) {
+        self.season = season.clamp(0.0, 1.0);
+    }
+
+    /// Defines the scene shaders are compiled with. Pipelines switch to the matching shader
+    /// permutation on the next frame.
+    pub fn shader_defines(&self) -> &ShaderDefines {
+        &self.shader_defines
+    }
+
+    pub fn set_shader_defines(&mut self, shader_defines: ShaderDefines) {
+        self.shader_defines = shader_defines;
+    }
+
+    /// Which files the scene and every pipeline depend on.
+    pub fn assets(&self) -> &AssetRegistry {
+        &self.assets
+    }
+
+    pub fn register_assets(&mut self, owner: AssetOwner, dependencies: Dependencies) {
+        self.assets.register(owner, dependencies);
+    }
+
+    /// Logs the error of a pipeline creation and shows its shader errors on screen until the
+    /// owning pipeline reloads without errors.
+    pub fn report_errors<T>(&self, result: Result<T, PipelineError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                log::error!("{}", err);
+                self.shader_errors.borrow_mut().report(&err);
+                None
+            }
+        }
+    }
+
+    /// The shader errors currently shown on screen.
+    pub fn shader_errors(&self) -> Vec<ShaderError> {
+        self.shader_errors.borrow().iter().cloned().collect()
+    }
+
+    pub(crate) fn begin_error_report(&self, owner: AssetOwner, reload: bool) {
+        self.shader_errors.borrow_mut().begin(owner, reload);
+    }
+
+    pub(crate) fn end_error_report(&self) {
+        self.shader_errors.borrow_mut().end();
+    }
+
+    pub fn render_graph(&self) -> &RenderGraph {
+        &self.render_graph
+    }
+
+    pub fn add_render_target(&mut self, id: TargetId, desc: TargetDescriptor) {
+        self.render_graph.add_target(&self.device, id, desc);
+    }
+
+    pub fn add_render_pass(&mut self, desc: PassDescriptor) {
+        self.render_graph.add_pass(desc);
+    }
+
+    pub fn remove_render_pass(&mut self, id: PassId) {
+        self.render_graph.remove_pass(id);
+    }
+
+    pub fn pass_formats(&self, pass: PassId) -> Option<PassFormats> {
+        self.render_graph.pass_formats(pass)
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+
+        self.size = new_size;
+        self.sc_desc.width = new_size.width;
+        self.sc_desc.height = new_size.height;
+        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+
+        self.render_graph.resize(&self.device, &self.sc_desc);
+
+        self.perspective_camera.aspect_ratio = new_size.width as f32 / new_size.height as f32;
+    }
+
+    pub fn keyboard_input_event(&mut self, event: &KeyboardInput) {
+        if let KeyboardInput {
+            virtual_keycode: Some(key),
+            state,
+            ..
+        } = *event
+        {
+            match state {
+                ElementState::Pressed => self.keyboard_input.activate(key),
+                ElementState::Released => self.keyboard_input.deactivate(key),
+            }
+        }
+    }
+
+    pub fn update(&mut self, delta: Duration, absolute_time: Duration) {
+        let difference: Vec3 = self.perspective_camera.at - self.perspective_camera.eye;
+        let forward: Vec3 = (difference).normalized();
+
+        let right = forward.cross(self.perspective_camera.up);
+
+        let elapsed_seconds = delta.as_secs_f32();
+
+        if self.keyboard_input.pressed(VirtualKeyCode::W) && difference.mag_sq() > 1.0 {
+            self.perspective_camera.eye += forward * elapsed_seconds;
+        }
+
+        if self.keyboard_input.pressed(VirtualKeyCode::S) {
+            self.perspective_camera.eye -= forward * elapsed_seconds;
+        }
+        if self.keyboard_input.pressed(VirtualKeyCode::A) {
+            self.perspective_camera.eye -= right * elapsed_seconds;
+        }
+        if self.keyboard_input.pressed(VirtualKeyCode::D) {
+            self.perspective_camera.eye += right * elapsed_seconds;
+        }
+        if self.keyboard_input.pressed(VirtualKeyCode::Q) {
+            self.perspective_camera.eye -= self.perspective_camera.up * elapsed_seconds;
+        }
+        if self.keyboard_input.pressed(VirtualKeyCode::E) {
+            self.perspective_camera.eye += self.perspective_camera.up * elapsed_seconds;
+        }
+
+        let light_view_proj = shadow::directional_light_view_proj(
+            self.lights.sun.direction,
+            SHADOW_CENTER,
+            SHADOW_RADIUS,
+        );
+        let uniforms = Uniforms::new(
+            &self.perspective_camera,
+            light_view_proj,
+            &self.fog,
+            self.season,
+            absolute_time.as_secs_f32(),
+        );
+        self.queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+        self.queue.write_buffer(
+            &self.shadow_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&Uniforms {
+                view_proj: light_view_proj,
+                ..uniforms
+            }),
+        );
+
+        self.keyboard_input.update();
+    }
+
+    pub fn files_changed(&mut self, changed: &HashSet<PathBuf>) {
+        let scene_changed = self
+            .assets
+            .dependencies(AssetOwner::Scene)
+            .map_or(false, |dependencies| dependencies.any_changed(changed));
+        if scene_changed {
+            if let Some(scene) = Scene::load(Scene::PATH) {
+                self.set_lights(scene.lights);
+                self.set_fog(scene.fog);
+                self.set_tone_mapping(scene.tone_mapping);
+                self.set_post_effects(scene.post_effects);
+                self.set_season(scene.season);
+                log::info!("Scene reloaded.");
+            }
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        pipelines: &mut [Box<dyn PipelineHandler>],
+    ) -> Result<(), wgpu::SwapChainError> {
+        let frame = self.swap_chain.get_current_frame()?.output;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        for pass in self.render_graph.ordered_passes() {
+            let view = |target: TargetId| {
+                if target == TargetId::SWAP_CHAIN {
+                    Some(&frame.view)
+                } else {
+                    self.render_graph
+                        .target(target)
+                        .map(|texture| &texture.view)
+                }
+            };
+
+            // Targets are validated when the pass is added, so a missing view is unexpected.
+            let color_attachments = match pass
+                .color_attachments
+                .iter()
+                .map(|attachment| {
+                    Some(wgpu::RenderPassColorAttachmentDescriptor {
+                        attachment: view(attachment.target)?,
+                        resolve_target: match attachment.resolve_target {
+                            Some(target) => Some(view(target)?),
+                            None => None,
+                        },
+                        ops: wgpu::Operations {
+                            load: attachment.load,
+                            store: true,
+                        },
+                    })
+                })
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(color_attachments) => color_attachments,
+                None => continue,
+            };
+            let depth_stencil_attachment = match pass.depth_attachment {
+                Some(ref attachment) => match view(attachment.target) {
+                    Some(view) => Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                        attachment: view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: attachment.load,
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    None => continue,
+                },
+                None => None,
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(pass.id.0),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment,
+            });
+
+            render_pass.execute_bundles(
+                pipelines
+                    .iter_mut()
+                    .filter(|p| p.passes().contains(&pass.id))
+                    .filter_map(|p| p.render_bundle(self, pass.id)),
+            );
+        }
+
+        let errors = self
+            .shader_errors
+            .borrow()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        self.error_overlay.render(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &self.sc_desc,
+            &frame.view,
+            &errors,
+        );
+
+        self.queue.submit(iter::once(encoder.finish()));
+
+        Ok(())
+    }
+}
+
+fn create_default_render_graph(
+    device: &wgpu::Device,
+    sc_desc: &wgpu::SwapChainDescriptor,
+) -> RenderGraph {
+    let mut render_graph = RenderGraph::new(sc_desc);
+
+    render_graph.add_target(
+        device,
+        TargetId::HDR,
+        TargetDescriptor {
+            format: Texture::HDR_FORMAT,
+            sample_count: 1,
+            size: TargetSize::SwapChain,
+        },
+    );
+
+    let (color_target, resolve_target) = if Texture::MSAA_SAMPLES > 1 {
+        render_graph.add_target(
+            device,
+            TargetId::COLOR,
+            TargetDescriptor {
+                format: Texture::HDR_FORMAT,
+                sample_count: Texture::MSAA_SAMPLES,
+                size: TargetSize::SwapChain,
+            },
+        );
+        (TargetId::COLOR, Some(TargetId::HDR))
+    } else {
+        (TargetId::HDR, None)
+    };
+
+    render_graph.add_target(
+        device,
+        TargetId::DEPTH,
+        TargetDescriptor {
+            format: Texture::DEPTH_FORMAT,
+            sample_count: Texture::MSAA_SAMPLES,
+            size: TargetSize::SwapChain,
+        },
+    );
+
+    shadow::add_shadow_pass(device, &mut render_graph);
+
+    // Without a sky pipeline the sky pass only clears the background.
+    render_graph.add_pass(PassDescriptor {
+        id: PassId::SKY,
+        color_attachments: vec![ColorAttachment {
+            target: color_target,
+            resolve_target: None,
+            load: wgpu::LoadOp::Clear(BACKGROUND_CLEAR_COLOR),
+        }],
+        depth_attachment: None,
+        dependencies: vec![],
+    });
+
+    render_graph.add_pass(PassDescriptor {
+        id: PassId::MAIN,
+        color_attachments: vec![ColorAttachment {
+            target: color_target,
+            resolve_target: None,
+            load: wgpu::LoadOp::Load,
+        }],
+        depth_attachment: Some(DepthAttachment {
+            target: TargetId::DEPTH,
+            load: wgpu::LoadOp::Clear(1.0),
+        }),
+        dependencies: vec![PassId::SHADOW, PassId::SKY],
+    });
+
+    render_graph.add_pass(PassDescriptor {
+        id: PassId::TRANSPARENT,
+        color_attachments: vec![ColorAttachment {
+            target: color_target,
+            resolve_target,
+            load: wgpu::LoadOp::Load,
+        }],
+        depth_attachment: Some(DepthAttachment {
+            target: TargetId::DEPTH,
+            load: wgpu::LoadOp::Load,
+        }),
+        dependencies: vec![PassId::MAIN],
+    });
+
+    render_graph.add_pass(PassDescriptor {
+        id: PassId::TONEMAP,
+        color_attachments: vec![ColorAttachment {
+            target: TargetId::SWAP_CHAIN,
+            resolve_target: None,
+            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        }],
+        depth_attachment: None,
+        dependencies: vec![PassId::TRANSPARENT],
+    });
+
+    render_graph
+}
@@ -1,145 +1,596 @@
+use std::borrow::Cow;
+use std::fmt;
+
+mod bc;
+mod container;
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
-#[allow(dead_code)]
-fn create_default_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
-    let size = wgpu::Extent3d {
-        width: 1,
-        height: 1,
-        depth: 1,
+/// Error returned when a texture can't be loaded from a file.
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Ktx2(ktx2::ParseError),
+    Dds(ddsfile::Error),
+    /// The container holds a format or layout the loader can't upload.
+    UnsupportedFormat(String),
+    /// A texture array was created without layers.
+    EmptyArray,
+    /// A texture array layer differs in size, format or mip count from the first layer.
+    LayerMismatch(std::path::PathBuf),
+    /// A container holds more mip levels than halving its size down to 1x1 gives.
+    TooManyMipLevels(usize),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(err) => write!(f, "failed to read texture: {}", err),
+            TextureError::Image(err) => write!(f, "failed to decode image: {}", err),
+            TextureError::Ktx2(err) => write!(f, "invalid KTX2 file: {}", err),
+            TextureError::Dds(err) => write!(f, "invalid DDS file: {}", err),
+            TextureError::UnsupportedFormat(what) => write!(f, "unsupported {}", what),
+            TextureError::EmptyArray => write!(f, "texture array has no layers"),
+            TextureError::LayerMismatch(path) => write!(
+                f,
+                "texture array layer {:?} doesn't match the first layer",
+                path
+            ),
+            TextureError::TooManyMipLevels(levels) => {
+                write!(
+                    f,
+                    "mip chain of {} levels is longer than the size allows",
+                    levels
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io(err) => Some(err),
+            TextureError::Image(err) => Some(err),
+            TextureError::Ktx2(err) => Some(err),
+            TextureError::Dds(err) => Some(err),
+            TextureError::UnsupportedFormat(_)
+            | TextureError::EmptyArray
+            | TextureError::LayerMismatch(_)
+            | TextureError::TooManyMipLevels(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TextureError {
+    fn from(err: std::io::Error) -> Self {
+        TextureError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(err: image::ImageError) -> Self {
+        TextureError::Image(err)
+    }
+}
+
+impl From<ktx2::ParseError> for TextureError {
+    fn from(err: ktx2::ParseError) -> Self {
+        TextureError::Ktx2(err)
+    }
+}
+
+impl From<ddsfile::Error> for TextureError {
+    fn from(err: ddsfile::Error) -> Self {
+        TextureError::Dds(err)
+    }
+}
+
+/// Sampler and mip settings for textures loaded from files.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    /// Maximum anisotropy, 1 disables anisotropic filtering. wgpu only takes 1, 2, 4, 8 or 16,
+    /// other values are rounded down to one of them.
+    pub anisotropy: u8,
+    /// Generates the full mip chain on the CPU when loading.
+    pub generate_mipmaps: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            anisotropy: 16,
+            generate_mipmaps: true,
+        }
+    }
+}
+
+impl TextureOptions {
+    fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: anisotropy_clamp(self.anisotropy),
+            ..Default::default()
+        })
+    }
+}
+
+/// The largest anisotropy wgpu accepts that doesn't exceed `anisotropy`.
+fn anisotropy_clamp(anisotropy: u8) -> Option<std::num::NonZeroU8> {
+    let clamp = match anisotropy {
+        0 | 1 => return None,
+        2..=3 => 2,
+        4..=7 => 4,
+        8..=15 => 8,
+        _ => 16,
+    };
+    if clamp != anisotropy {
+        log::warn!(
+            "Anisotropy {} isn't supported, using {}.",
+            anisotropy,
+            clamp
+        );
+    }
+    std::num::NonZeroU8::new(clamp)
+}
+
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Picks the upload format for an image, converting it first when wgpu has no matching format.
+/// Color images are sRGB encoded and grayscale ones linear. 16-bit channels are uploaded as half
+/// floats since wgpu has no filterable 16-bit unorm format, with color decoded to linear first.
+fn texture_format(image: image::DynamicImage) -> (image::DynamicImage, wgpu::TextureFormat) {
+    use image::DynamicImage;
+    match image {
+        DynamicImage::ImageLuma8(_) => (image, wgpu::TextureFormat::R8Unorm),
+        DynamicImage::ImageLumaA8(_) => (image, wgpu::TextureFormat::Rg8Unorm),
+        DynamicImage::ImageRgba8(_) => (image, wgpu::TextureFormat::Rgba8UnormSrgb),
+        DynamicImage::ImageBgra8(_) => (image, wgpu::TextureFormat::Bgra8UnormSrgb),
+        DynamicImage::ImageLuma16(_) => (image, wgpu::TextureFormat::R16Float),
+        DynamicImage::ImageLumaA16(_) => (image, wgpu::TextureFormat::Rg16Float),
+        DynamicImage::ImageRgba16(_) => (image, wgpu::TextureFormat::Rgba16Float),
+        DynamicImage::ImageRgb16(_) => (
+            DynamicImage::ImageRgba16(image.into_rgba16()),
+            wgpu::TextureFormat::Rgba16Float,
+        ),
+        _ => (
+            DynamicImage::ImageRgba8(image.into_rgba8()),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        ),
+    }
+}
+
+/// Converts a value in [0, 1] to the bits of a half float, so there is no overflow or NaN to
+/// handle.
+fn unorm_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        // Subnormal half, or zero.
+        if exponent < -10 {
+            return 0;
+        }
+        return ((mantissa | 0x80_0000) >> (14 - exponent)) as u16;
+    }
+    // Rounding may carry into the exponent, which is still the correct result.
+    (((exponent as u32) << 10) + ((mantissa + 0x1000) >> 13)) as u16
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The texels of a color image in linear space, alpha last, or `None` for grayscale images.
+fn linear_rgba(
+    image: &image::DynamicImage,
+) -> Option<image::ImageBuffer<image::Rgba<f32>, Vec<f32>>> {
+    use image::DynamicImage;
+    fn decode<T: Copy + Into<f32>>(texels: &[T], max: f32) -> Vec<f32> {
+        let mut linear = Vec::with_capacity(texels.len());
+        for texel in texels.chunks_exact(4) {
+            for &channel in &texel[..3] {
+                linear.push(srgb_to_linear(channel.into() / max));
+            }
+            linear.push(texel[3].into() / max);
+        }
+        linear
+    }
+    let linear = match image {
+        DynamicImage::ImageRgba8(buf) => decode(buf.as_raw(), 255.0),
+        DynamicImage::ImageBgra8(buf) => decode(buf.as_raw(), 255.0),
+        DynamicImage::ImageRgba16(buf) => decode(buf.as_raw(), 65535.0),
+        _ => return None,
     };
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("Default Texture"),
-        size,
-        mip_level_count: 1,
-        sample_count: Texture::MSAA_SAMPLES,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-    });
-
-    let rgba: [u8; 4] = [255, 20, 150, 255];
+    use image::GenericImageView;
+    let (width, height) = image.dimensions();
+    image::ImageBuffer::from_raw(width, height, linear)
+}
+
+/// Bytes of half floats for values in [0, 1].
+fn half_floats(values: impl ExactSizeIterator<Item = f32>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(values.len() * 2);
+    for value in values {
+        bytes.extend_from_slice(&unorm_to_f16(value).to_ne_bytes());
+    }
+    bytes
+}
+
+/// Encodes linear texels with alpha last for a color format returned by `texture_format`.
+fn encode_linear(texels: &[f32], format: wgpu::TextureFormat) -> Vec<u8> {
+    if format == wgpu::TextureFormat::Rgba16Float {
+        return half_floats(texels.iter().map(|&value| value.min(1.0)));
+    }
+    let mut encoded = Vec::with_capacity(texels.len());
+    for texel in texels.chunks_exact(4) {
+        for &channel in &texel[..3] {
+            encoded.push((linear_to_srgb(channel) * 255.0).round().min(255.0) as u8);
+        }
+        encoded.push((texel[3] * 255.0).round().min(255.0) as u8);
+    }
+    encoded
+}
+
+/// Texel data of an image laid out for the format returned by `texture_format`.
+fn texel_data(image: &image::DynamicImage) -> Cow<'_, [u8]> {
+    use image::DynamicImage;
+    let raw = match image {
+        DynamicImage::ImageLuma16(buf) => buf.as_raw(),
+        DynamicImage::ImageLumaA16(buf) => buf.as_raw(),
+        DynamicImage::ImageRgba16(_) => {
+            let linear = linear_rgba(image).map(|image| image.into_raw());
+            let format = wgpu::TextureFormat::Rgba16Float;
+            return Cow::Owned(encode_linear(&linear.unwrap_or_default(), format));
+        }
+        _ => return Cow::Borrowed(image.as_bytes()),
+    };
+    Cow::Owned(half_floats(raw.iter().map(|&value| value as f32 / 65535.0)))
+}
+
+/// Uploads one mip level of tightly packed texels, padding rows to
+/// `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT` when needed. Compressed formats are copied in whole
+/// blocks, so the copy covers the physical size of levels smaller than a block.
+fn write_mip_level(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    layer: u32,
+    mip_level: u32,
+    format: wgpu::TextureFormat,
+    size: wgpu::Extent3d,
+    data: &[u8],
+) {
+    let info = format.describe();
+    let (block_width, block_height) = (
+        info.block_dimensions.0 as u32,
+        info.block_dimensions.1 as u32,
+    );
+    let blocks_wide = (size.width + block_width - 1) / block_width;
+    let blocks_high = (size.height + block_height - 1) / block_height;
+
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let unpadded_bytes_per_row = blocks_wide * info.block_size as u32;
+    let bytes_per_row = unpadded_bytes_per_row + (align - unpadded_bytes_per_row % align) % align;
+
+    let data = if bytes_per_row == unpadded_bytes_per_row {
+        Cow::Borrowed(data)
+    } else {
+        let mut padded = vec![0; (bytes_per_row * blocks_high) as usize];
+        for (src, dst) in data
+            .chunks_exact(unpadded_bytes_per_row as usize)
+            .zip(padded.chunks_exact_mut(bytes_per_row as usize))
+        {
+            dst[..src.len()].copy_from_slice(src);
+        }
+        Cow::Owned(padded)
+    };
+
     queue.write_texture(
         wgpu::TextureCopyView {
-            texture: &texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
         },
-        &rgba,
+        &data,
         wgpu::TextureDataLayout {
             offset: 0,
-            bytes_per_row: 4 * size.width,
-            rows_per_image: size.height,
+            bytes_per_row,
+            rows_per_image: blocks_high * block_height,
+        },
+        wgpu::Extent3d {
+            width: blocks_wide * block_width,
+            height: blocks_high * block_height,
+            depth: 1,
         },
-        size,
     );
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-        address_mode_u: wgpu::AddressMode::Repeat,
-        address_mode_v: wgpu::AddressMode::Repeat,
-        address_mode_w: wgpu::AddressMode::Repeat,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::FilterMode::Nearest,
-        ..Default::default()
-    });
+}
+
+/// Filters `image`, already converted to `format`, down to `mip_level_count` tightly packed
+/// levels, base level first.
+fn generate_mips(
+    mut image: image::DynamicImage,
+    format: wgpu::TextureFormat,
+    mip_level_count: u32,
+) -> Vec<Vec<u8>> {
+    use image::GenericImageView;
+    let (width, height) = image.dimensions();
+    let mut levels = vec![texel_data(&image).into_owned()];
+    // Each level is filtered down from the previous one. Color is filtered in linear space, as
+    // averaging sRGB values would make every level darker than the one above.
+    let filter = image::imageops::FilterType::Triangle;
+    match linear_rgba(&image) {
+        Some(mut linear) => {
+            for mip_level in 1..mip_level_count {
+                let level_width = (width >> mip_level).max(1);
+                let level_height = (height >> mip_level).max(1);
+                linear = image::imageops::resize(&linear, level_width, level_height, filter);
+                levels.push(encode_linear(linear.as_raw(), format));
+            }
+        }
+        None => {
+            for mip_level in 1..mip_level_count {
+                let level_width = (width >> mip_level).max(1);
+                let level_height = (height >> mip_level).max(1);
+                image = image.resize_exact(level_width, level_height, filter);
+                levels.push(texel_data(&image).into_owned());
+            }
+        }
+    }
+    levels
+}
 
-    Texture {
-        texture,
-        view,
-        sampler,
+/// Tightly packed mip levels of one image, base level first.
+struct MipChain {
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    levels: Vec<Vec<u8>>,
+}
+
+/// Reads an image file, or a KTX2 or DDS container. Containers keep the mip levels they hold,
+/// `options.generate_mipmaps` only applies to other images.
+fn load_mip_chain(
+    path: &std::path::Path,
+    options: &TextureOptions,
+) -> Result<MipChain, TextureError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("ktx2") => return container::load_ktx2(&std::fs::read(path)?),
+        Some("dds") => return container::load_dds(&std::fs::read(path)?),
+        _ => {}
     }
+
+    let (image, format) = texture_format(image::open(path)?);
+
+    use image::GenericImageView;
+    let (width, height) = image.dimensions();
+    let mip_level_count = if options.generate_mipmaps {
+        mip_level_count(width, height)
+    } else {
+        1
+    };
+
+    let levels = generate_mips(image, format, mip_level_count);
+    Ok(MipChain {
+        format,
+        width,
+        height,
+        levels,
+    })
 }
 
 impl Texture {
-    #[allow(dead_code)]
-    pub fn create<P: AsRef<std::path::Path>>(
+    /// A 1x1 magenta texture for callers to use when loading fails. `view_dimension` is `D2`
+    /// or `D2Array`, matching the texture it stands in for.
+    pub fn create_fallback(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        path: P,
+        view_dimension: wgpu::TextureViewDimension,
     ) -> Texture {
-        let mut image = match image::open(path.as_ref()) {
-            Ok(img) => img,
-            Err(err) => {
-                let p: &std::path::Path = path.as_ref();
-                log::error!("Failed to load texture {:?} {:?}", p, err);
-                return create_default_texture(device, queue);
-            }
+        let size = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth: 1,
         };
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Fallback Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        let rgba: [u8; 4] = [255, 20, 150, 255];
+        write_mip_level(queue, &texture, 0, 0, format, size, &rgba);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(view_dimension),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Texture {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Loads an image file, or a KTX2 or DDS container.
+    pub fn create<P: AsRef<std::path::Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+        options: &TextureOptions,
+    ) -> Result<Texture, TextureError> {
+        let layer = load_mip_chain(path.as_ref(), options)?;
+        let dimension = wgpu::TextureViewDimension::D2;
+        Self::create_from_layers(device, queue, vec![layer], dimension, options)
+    }
 
-        let format = match image.color() {
-            image::ColorType::L8 => wgpu::TextureFormat::R8Unorm,
-            image::ColorType::La8 => wgpu::TextureFormat::Rg8Unorm,
-            image::ColorType::Rgba8 => wgpu::TextureFormat::Rgba8UnormSrgb,
-            image::ColorType::L16 => wgpu::TextureFormat::R16Uint,
-            image::ColorType::La16 => wgpu::TextureFormat::Rg16Uint,
-            image::ColorType::Rgba16 => wgpu::TextureFormat::Rgba16Uint,
-            image::ColorType::Bgra8 => wgpu::TextureFormat::Bgra8UnormSrgb,
-            _ => {
-                image = image::DynamicImage::ImageRgba8(image.into_rgba8());
-                wgpu::TextureFormat::Rgba8UnormSrgb
+    /// Loads one file per layer into a 2D texture array. The layers must share their size,
+    /// format and mip count.
+    pub fn create_array<P: AsRef<std::path::Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[P],
+        options: &TextureOptions,
+    ) -> Result<Texture, TextureError> {
+        let layers = paths
+            .iter()
+            .map(|path| load_mip_chain(path.as_ref(), options))
+            .collect::<Result<Vec<_>, _>>()?;
+        let first = layers.first().ok_or(TextureError::EmptyArray)?;
+        for (path, layer) in paths.iter().zip(layers.iter()).skip(1) {
+            if (layer.format, layer.width, layer.height, layer.levels.len())
+                != (first.format, first.width, first.height, first.levels.len())
+            {
+                return Err(TextureError::LayerMismatch(path.as_ref().to_owned()));
             }
-        };
+        }
+        let dimension = wgpu::TextureViewDimension::D2Array;
+        Self::create_from_layers(device, queue, layers, dimension, options)
+    }
+
+    fn create_from_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut layers: Vec<MipChain>,
+        view_dimension: wgpu::TextureViewDimension,
+        options: &TextureOptions,
+    ) -> Result<Texture, TextureError> {
+        let (mut format, width, height) = (layers[0].format, layers[0].width, layers[0].height);
 
-        use image::GenericImageView;
-        let (width, height) = image.dimensions();
+        // wgpu only copies whole blocks, so a base level that isn't a multiple of the block size
+        // is decoded as well.
+        let (block_width, block_height) = format.describe().block_dimensions;
+        let compressed = (block_width, block_height) != (1, 1);
+        let supported = device
+            .features()
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+            && width % block_width as u32 == 0
+            && height % block_height as u32 == 0;
+        if compressed && !supported {
+            let compressed_format = format;
+            for layer in layers.iter_mut() {
+                for (level, data) in layer.levels.iter_mut().enumerate() {
+                    let level_width = (width >> level).max(1);
+                    let level_height = (height >> level).max(1);
+                    let decoded = bc::decode(compressed_format, level_width, level_height, data);
+                    let (decoded_format, texels) = decoded.ok_or_else(|| {
+                        TextureError::UnsupportedFormat(format!(
+                            "compressed format {:?}",
+                            compressed_format
+                        ))
+                    })?;
+                    *data = texels;
+                    format = decoded_format;
+                }
+            }
+        }
 
         let size = wgpu::Extent3d {
             width,
             height,
-            depth: 1,
+            depth: layers.len() as u32,
         };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
-            // All textures are stored as 3D, we represent our 2D texture
-            // by setting depth to 1.
+            // A D2 texture, so `size.depth` is its number of array layers.
             size,
-            mip_level_count: 1, // We'll talk about this a little later
+            mip_level_count: layers[0].levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: format,
+            format,
             // SAMPLED tells wgpu that we want to use this texture in shaders
             // COPY_DST means that we want to copy data to this texture
             usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
             label: Some("diffuse_texture"),
         });
 
-        queue.write_texture(
-            wgpu::TextureCopyView {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            image.as_bytes(),
-            wgpu::TextureDataLayout {
-                offset: 0,
-                bytes_per_row: 4 * size.width,
-                rows_per_image: size.height,
-            },
-            size,
-        );
+        for (layer, chain) in layers.iter().enumerate() {
+            for (level, data) in chain.levels.iter().enumerate() {
+                let size = wgpu::Extent3d {
+                    width: (width >> level).max(1),
+                    height: (height >> level).max(1),
+                    depth: 1,
+                };
+                write_mip_level(
+                    queue,
+                    &texture,
+                    layer as u32,
+                    level as u32,
+                    format,
+                    size,
+                    data,
+                );
+            }
+        }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(view_dimension),
             ..Default::default()
         });
+        let sampler = options.create_sampler(device);
 
-        Texture {
+        Ok(Texture {
             texture,
             view,
             sampler,
-        }
+        })
     }
 
     pub const DEPTH_FORMAT : wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
     pub const MSAA_SAMPLES : u32 = 4;
     
     pub fn create_depth_texture(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> Self {
@@ -148,18 +599,37 @@ impl Texture {
             height: sc_desc.height,
             depth: 1,
         };
+        Self::create_render_target(
+            device,
+            "Depth Texture",
+            size,
+            Texture::DEPTH_FORMAT,
+            Texture::MSAA_SAMPLES,
+        )
+    }
+
+    /// Creates a texture that can be rendered to and sampled. Depth formats get a comparison
+    /// sampler.
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        label: &str,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
         let desc = wgpu::TextureDescriptor {
-            label: Some("Depth Texture"),
+            label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: Texture::MSAA_SAMPLES,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
-            format: Texture::DEPTH_FORMAT,
+            format,
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT // 3.
                 | wgpu::TextureUsage::SAMPLED,
         };
         let texture = device.create_texture(&desc);
 
+        let is_depth = format.describe().sample_type == wgpu::TextureSampleType::Depth;
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(
             &wgpu::SamplerDescriptor { // 4.
@@ -169,7 +639,7 @@ impl Texture {
                 mag_filter: wgpu::FilterMode::Linear,
                 min_filter: wgpu::FilterMode::Linear,
                 mipmap_filter: wgpu::FilterMode::Nearest,
-                compare: Some(wgpu::CompareFunction::LessEqual), // 5.
+                compare: if is_depth { Some(wgpu::CompareFunction::LessEqual) } else { None }, // 5.
                 lod_min_clamp: -100.0,
                 lod_max_clamp: 100.0,
                 ..Default::default()
@@ -179,3 +649,67 @@ impl Texture {
         Self { texture, view, sampler }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anisotropy_rounds_down_to_supported_values() {
+        let clamps: Vec<Option<u8>> = [0, 1, 2, 3, 4, 5, 8, 12, 16, 32, 255]
+            .iter()
+            .map(|&anisotropy| anisotropy_clamp(anisotropy).map(|clamp| clamp.get()))
+            .collect();
+        assert_eq!(
+            clamps,
+            [
+                None,
+                None,
+                Some(2),
+                Some(2),
+                Some(4),
+                Some(4),
+                Some(8),
+                Some(8),
+                Some(16),
+                Some(16),
+                Some(16)
+            ]
+        );
+    }
+
+    #[test]
+    fn srgb_mips_average_in_linear_space() {
+        // Black and white average to linear 0.5, which is 188 in sRGB rather than 128.
+        let texels = vec![0, 0, 0, 0, 255, 255, 255, 255];
+        let image = image::RgbaImage::from_raw(2, 1, texels).unwrap();
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let levels = generate_mips(image::DynamicImage::ImageRgba8(image), format, 2);
+        assert_eq!(levels[1], [188, 188, 188, 128]);
+
+        // Mips of a flat color stay that color.
+        let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([90, 160, 30, 200]));
+        let levels = generate_mips(image::DynamicImage::ImageRgba8(image), format, 3);
+        assert_eq!(levels[1], [90, 160, 30, 200].repeat(4));
+        assert_eq!(levels[2], [90, 160, 30, 200]);
+    }
+
+    #[test]
+    fn sixteen_bit_color_is_decoded_to_linear() {
+        let half = |bytes: &[u8]| u16::from_ne_bytes([bytes[0], bytes[1]]);
+        let texel = image::Rgba([0x8080u16, 0xffff, 0, 0x8000]);
+        let image = image::ImageBuffer::from_pixel(1, 1, texel);
+        let (image, format) = texture_format(image::DynamicImage::ImageRgba16(image));
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Float);
+        let data = texel_data(&image);
+        // sRGB 0.502 is linear 0.2158, alpha stays 0.5.
+        let halves: Vec<u16> = data.chunks_exact(2).map(half).collect();
+        assert_eq!(halves, [unorm_to_f16(0.2158), 0x3c00, 0, 0x3800]);
+
+        // Grayscale stays linear.
+        let image = image::ImageBuffer::from_pixel(1, 1, image::Luma([0x8000u16]));
+        let (image, format) = texture_format(image::DynamicImage::ImageLuma16(image));
+        assert_eq!(format, wgpu::TextureFormat::R16Float);
+        assert_eq!(half(&texel_data(&image)), 0x3800);
+    }
+}
@@ -0,0 +1,798 @@
+//! CPU decoding of BC compressed blocks, for adapters without `TEXTURE_COMPRESSION_BC`.
+
+use std::convert::TryInto;
+
+type Block = [[u8; 4]; 16];
+
+/// Decodes a mip level of BC1, BC3, BC5 or BC7 blocks into tightly packed texels. Returns the
+/// uncompressed format with the data, or `None` when there is no decoder for `format`.
+pub(super) fn decode(
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Option<(wgpu::TextureFormat, Vec<u8>)> {
+    use wgpu::TextureFormat::*;
+    let (decode_block, block_size, decoded_format): (fn(&[u8]) -> Block, usize, _) = match format {
+        Bc1RgbaUnorm => (decode_bc1, 8, Rgba8Unorm),
+        Bc1RgbaUnormSrgb => (decode_bc1, 8, Rgba8UnormSrgb),
+        Bc3RgbaUnorm => (decode_bc3, 16, Rgba8Unorm),
+        Bc3RgbaUnormSrgb => (decode_bc3, 16, Rgba8UnormSrgb),
+        Bc5RgUnorm => (decode_bc5, 16, Rg8Unorm),
+        Bc7RgbaUnorm => (decode_bc7, 16, Rgba8Unorm),
+        Bc7RgbaUnormSrgb => (decode_bc7, 16, Rgba8UnormSrgb),
+        _ => return None,
+    };
+    let channels = decoded_format.describe().block_size as usize;
+
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = (width + 3) / 4;
+    let mut texels = vec![0; width * height * channels];
+    for (i, block) in data.chunks_exact(block_size).enumerate() {
+        let (block_x, block_y) = (i % blocks_wide * 4, i / blocks_wide * 4);
+        for (j, texel) in decode_block(block).iter().enumerate() {
+            let (x, y) = (block_x + j % 4, block_y + j / 4);
+            // Blocks on the right and bottom edge may cover texels outside the image.
+            if x < width && y < height {
+                let offset = (y * width + x) * channels;
+                texels[offset..offset + channels].copy_from_slice(&texel[..channels]);
+            }
+        }
+    }
+    Some((decoded_format, texels))
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let r = (color >> 11) as u8 & 0x1f;
+    let g = (color >> 5) as u8 & 0x3f;
+    let b = color as u8 & 0x1f;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+}
+
+/// The colour half of BC1 and BC3. Only BC1 has the three colour mode with transparent black.
+fn decode_color_block(block: &[u8], allow_transparent: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+
+    let mut palette = [e0, e1, [0, 0, 0, 255], [0, 0, 0, 0]];
+    for c in 0..3 {
+        let (a, b) = (e0[c] as u32, e1[c] as u32);
+        if c0 > c1 || !allow_transparent {
+            palette[2][c] = ((2 * a + b) / 3) as u8;
+            palette[3][c] = ((a + 2 * b) / 3) as u8;
+        } else {
+            palette[2][c] = ((a + b) / 2) as u8;
+        }
+    }
+    if c0 > c1 || !allow_transparent {
+        palette[3][3] = 255;
+    }
+
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[(indices >> (2 * i)) as usize & 3];
+    }
+    texels
+}
+
+/// A single channel block, used for BC3 alpha and both BC5 channels.
+fn decode_bc4(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [block[0], block[1], 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for (k, value) in palette.iter_mut().enumerate().skip(2) {
+            let k = k as u32;
+            *value = (((8 - k) * a0 + (k - 1) * a1) / 7) as u8;
+        }
+    } else {
+        for (k, value) in palette.iter_mut().enumerate().take(6).skip(2) {
+            let k = k as u32;
+            *value = (((6 - k) * a0 + (k - 1) * a1) / 5) as u8;
+        }
+    }
+
+    let mut bytes = [0; 8];
+    bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bytes);
+    let mut values = [0; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[(indices >> (3 * i)) as usize & 7];
+    }
+    values
+}
+
+fn decode_bc1(block: &[u8]) -> Block {
+    decode_color_block(block, true)
+}
+
+fn decode_bc3(block: &[u8]) -> Block {
+    let alpha = decode_bc4(&block[..8]);
+    let mut texels = decode_color_block(&block[8..], false);
+    for (texel, alpha) in texels.iter_mut().zip(alpha.iter()) {
+        texel[3] = *alpha;
+    }
+    texels
+}
+
+fn decode_bc5(block: &[u8]) -> Block {
+    let red = decode_bc4(&block[..8]);
+    let green = decode_bc4(&block[8..]);
+    let mut texels = [[0, 0, 0, 255]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[0] = red[i];
+        texel[1] = green[i];
+    }
+    texels
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// One P-bit per endpoint.
+    endpoint_p_bits: bool,
+    /// One P-bit per subset, shared by both its endpoints.
+    shared_p_bits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: true,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
+
+/// Two subset partitions, bit `i` set when texel `i` is in the second subset.
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Three subset partitions, two bits per texel.
+const BC7_PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// Anchor texel of the second subset in two subset partitions.
+const BC7_ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor texels of the second and third subset in three subset partitions.
+const BC7_ANCHORS_3: [[u8; 2]; 64] = [
+    [3, 15],
+    [3, 8],
+    [15, 8],
+    [15, 3],
+    [8, 15],
+    [3, 15],
+    [15, 3],
+    [15, 8],
+    [8, 15],
+    [8, 15],
+    [6, 15],
+    [6, 15],
+    [6, 15],
+    [5, 15],
+    [3, 15],
+    [3, 8],
+    [3, 15],
+    [3, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [3, 8],
+    [6, 15],
+    [10, 8],
+    [5, 3],
+    [8, 15],
+    [8, 6],
+    [6, 10],
+    [8, 15],
+    [5, 15],
+    [15, 10],
+    [15, 8],
+    [8, 15],
+    [15, 3],
+    [3, 15],
+    [5, 10],
+    [6, 10],
+    [10, 8],
+    [8, 9],
+    [15, 10],
+    [15, 6],
+    [3, 15],
+    [15, 8],
+    [5, 15],
+    [15, 3],
+    [15, 6],
+    [15, 6],
+    [15, 8],
+    [3, 15],
+    [15, 3],
+    [5, 15],
+    [5, 15],
+    [5, 15],
+    [8, 15],
+    [5, 15],
+    [10, 15],
+    [5, 15],
+    [10, 15],
+    [8, 15],
+    [13, 15],
+    [15, 3],
+    [12, 15],
+    [3, 15],
+    [3, 8],
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+struct BitReader {
+    bits: u128,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits & ((1 << count) - 1)) as u32;
+        self.bits >>= count;
+        value
+    }
+}
+
+fn bc7_subset(subsets: usize, partition: usize, texel: usize) -> usize {
+    match subsets {
+        2 => (BC7_PARTITIONS_2[partition] >> texel) as usize & 1,
+        3 => (BC7_PARTITIONS_3[partition] >> (2 * texel)) as usize & 3,
+        _ => 0,
+    }
+}
+
+fn bc7_is_anchor(subsets: usize, partition: usize, texel: usize) -> bool {
+    texel == 0
+        || match subsets {
+            2 => BC7_ANCHORS_2[partition] as usize == texel,
+            3 => BC7_ANCHORS_3[partition].contains(&(texel as u8)),
+            _ => false,
+        }
+}
+
+fn bc7_interpolate(e0: u8, e1: u8, index: u32, index_bits: u32) -> u8 {
+    let weight = match index_bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    };
+    (((64 - weight) * e0 as u32 + weight * e1 as u32 + 32) >> 6) as u8
+}
+
+fn decode_bc7(block: &[u8]) -> Block {
+    let mut bits = BitReader {
+        bits: u128::from_le_bytes(block.try_into().unwrap()),
+    };
+    let mode = match (0..8).find(|_| bits.read(1) == 1) {
+        Some(mode) => &BC7_MODES[mode],
+        // Reserved mode, decodes to transparent black.
+        None => return [[0; 4]; 16],
+    };
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let (mut color_bits, mut alpha_bits) = (mode.color_bits, mode.alpha_bits);
+    if mode.endpoint_p_bits || mode.shared_p_bits {
+        let mut p_bit = 0;
+        for (i, endpoint) in endpoints.iter_mut().take(endpoint_count).enumerate() {
+            if mode.endpoint_p_bits || i % 2 == 0 {
+                p_bit = bits.read(1);
+            }
+            for value in endpoint.iter_mut() {
+                *value = *value << 1 | p_bit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    let mut expanded = [[255u8; 4]; 6];
+    for (endpoint, expanded) in endpoints
+        .iter()
+        .zip(expanded.iter_mut())
+        .take(endpoint_count)
+    {
+        for (channel, value) in endpoint.iter().enumerate() {
+            let bits = if channel < 3 { color_bits } else { alpha_bits };
+            if bits > 0 {
+                let value = value << (8 - bits);
+                expanded[channel] = (value | value >> bits) as u8;
+            }
+        }
+    }
+
+    // Anchor texels store their index with the top bit left out, since it is always zero.
+    let mut indices = [0; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        let anchor = bc7_is_anchor(mode.subsets, partition, texel);
+        *index = bits.read(mode.index_bits - anchor as u32);
+    }
+    let mut secondary_indices = [0; 16];
+    if mode.secondary_index_bits > 0 {
+        for (texel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = bits.read(mode.secondary_index_bits - (texel == 0) as u32);
+        }
+    }
+
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let subset = bc7_subset(mode.subsets, partition, i);
+        let (e0, e1) = (expanded[2 * subset], expanded[2 * subset + 1]);
+        let (color_index, color_index_bits, alpha_index, alpha_index_bits) =
+            if mode.secondary_index_bits == 0 {
+                (indices[i], mode.index_bits, indices[i], mode.index_bits)
+            } else if index_selection == 0 {
+                let secondary = (secondary_indices[i], mode.secondary_index_bits);
+                (indices[i], mode.index_bits, secondary.0, secondary.1)
+            } else {
+                let secondary = (secondary_indices[i], mode.secondary_index_bits);
+                (secondary.0, secondary.1, indices[i], mode.index_bits)
+            };
+        for channel in 0..3 {
+            texel[channel] =
+                bc7_interpolate(e0[channel], e1[channel], color_index, color_index_bits);
+        }
+        texel[3] = bc7_interpolate(e0[3], e1[3], alpha_index, alpha_index_bits);
+        if rotation > 0 {
+            texel.swap(3, rotation as usize - 1);
+        }
+    }
+    texels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out a BC7 block the way an encoder does: the unary mode prefix, then each field least
+    /// significant bit first. Fields are `(value, bits)` and must fill the block exactly.
+    fn bc7_block(mode: u32, fields: &[(u32, u32)]) -> [u8; 16] {
+        let mut bits = 1u128 << mode;
+        let mut offset = mode + 1;
+        for &(value, count) in fields {
+            assert!(
+                value < 1 << count,
+                "{} does not fit in {} bits",
+                value,
+                count
+            );
+            bits |= (value as u128) << offset;
+            offset += count;
+        }
+        assert_eq!(offset, 128);
+        bits.to_le_bytes()
+    }
+
+    /// Endpoint fields in block order: red of every endpoint, then green, blue and alpha.
+    fn bc7_endpoints(
+        colors: &[[u32; 3]],
+        color_bits: u32,
+        alphas: &[u32],
+        alpha_bits: u32,
+    ) -> Vec<(u32, u32)> {
+        let mut fields = Vec::new();
+        for channel in 0..3 {
+            fields.extend(colors.iter().map(|color| (color[channel], color_bits)));
+        }
+        fields.extend(alphas.iter().map(|&alpha| (alpha, alpha_bits)));
+        fields
+    }
+
+    /// Index fields, with one bit less for the texels in `anchors`.
+    fn bc7_indices(indices: [u32; 16], bits: u32, anchors: &[usize]) -> Vec<(u32, u32)> {
+        let anchor_bits = |texel| bits - anchors.contains(&texel) as u32;
+        (0..16)
+            .map(|texel| (indices[texel], anchor_bits(texel)))
+            .collect()
+    }
+
+    #[test]
+    fn bc1_four_and_three_color_blocks() {
+        // Red and blue endpoints, indices 0, 1, 2, 3 for the first four texels.
+        let texels = decode_bc1(&[0x00, 0xf8, 0x1f, 0x00, 0xe4, 0, 0, 0]);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1], [0, 0, 255, 255]);
+        assert_eq!(texels[2], [170, 0, 85, 255]);
+        assert_eq!(texels[3], [85, 0, 170, 255]);
+        assert_eq!(texels[15], [255, 0, 0, 255]);
+
+        // Swapping the endpoints selects the midpoint and transparent black.
+        let texels = decode_bc1(&[0x1f, 0x00, 0x00, 0xf8, 0xe4, 0, 0, 0]);
+        assert_eq!(texels[0], [0, 0, 255, 255]);
+        assert_eq!(texels[1], [255, 0, 0, 255]);
+        assert_eq!(texels[2], [127, 0, 127, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rgb565_replicates_high_bits() {
+        assert_eq!(rgb565(0x7bef), [123, 125, 123, 255]);
+        assert_eq!(rgb565(0xffff), [255, 255, 255, 255]);
+        assert_eq!(rgb565(0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc4_eight_and_six_value_blocks() {
+        // Indices 0, 1, 2 and 7 for the first four texels.
+        let values = decode_bc4(&[200, 100, 0x88, 0x0e, 0, 0, 0, 0]);
+        assert_eq!(values[..4], [200, 100, 185, 114]);
+
+        // Indices 2, 6 and 7, where 6 and 7 are the constants 0 and 255.
+        let values = decode_bc4(&[50, 150, 0xf2, 0x01, 0, 0, 0, 0]);
+        assert_eq!(values[..4], [70, 0, 255, 50]);
+    }
+
+    #[test]
+    fn bc3_alpha_with_four_color_block() {
+        let mut block = [200, 100, 0x88, 0x0e, 0, 0, 0, 0].to_vec();
+        // Blue below red, which BC1 would decode as three colors.
+        block.extend_from_slice(&[0x1f, 0x00, 0x00, 0xf8, 0xe4, 0, 0, 0]);
+        let texels = decode_bc3(&block);
+        assert_eq!(texels[0], [0, 0, 255, 200]);
+        assert_eq!(texels[2], [85, 0, 170, 185]);
+        assert_eq!(texels[3], [170, 0, 85, 114]);
+    }
+
+    #[test]
+    fn bc5_red_and_green_blocks() {
+        let mut block = [200, 100, 0x88, 0x0e, 0, 0, 0, 0].to_vec();
+        block.extend_from_slice(&[50, 150, 0xf2, 0x01, 0, 0, 0, 0]);
+        let texels = decode_bc5(&block);
+        assert_eq!(
+            texels[..4],
+            [
+                [200, 70, 0, 255],
+                [100, 0, 0, 255],
+                [185, 255, 0, 255],
+                [114, 50, 0, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn bc7_mode_0() {
+        // Partition 0 puts texels 2, 3, 6, 7 and 11 in subset 1 and 9, 10 and 12-15 in subset 2.
+        let colors = [
+            [0, 0, 0],
+            [15, 15, 15],
+            [8, 4, 2],
+            [8, 4, 2],
+            [0, 0, 0],
+            [15, 0, 0],
+        ];
+        let mut fields = vec![(0, 4)];
+        fields.extend(bc7_endpoints(&colors, 4, &[], 0));
+        fields.extend([0, 1, 1, 1, 0, 0].iter().map(|&p_bit| (p_bit, 1)));
+        let mut indices = [0; 16];
+        indices[1] = 7;
+        indices[9] = 4;
+        fields.extend(bc7_indices(indices, 3, &[0, 3, 15]));
+        let texels = decode_bc7(&bc7_block(0, &fields));
+        assert_eq!(texels[0], [0, 0, 0, 255]);
+        assert_eq!(texels[1], [255, 255, 255, 255]);
+        assert_eq!(texels[2], [140, 74, 41, 255]);
+        assert_eq!(texels[11], [140, 74, 41, 255]);
+        assert_eq!(texels[9], [143, 0, 0, 255]);
+        assert_eq!(texels[15], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_1() {
+        // Partition 13 puts the bottom two rows in subset 1, with the P-bits shared per subset.
+        let colors = [[63, 0, 0], [0, 0, 63], [0, 0, 0], [63, 63, 63]];
+        let mut fields = vec![(13, 6)];
+        fields.extend(bc7_endpoints(&colors, 6, &[], 0));
+        fields.extend(&[(1, 1), (0, 1)]);
+        let mut indices = [0; 16];
+        indices[1] = 7;
+        indices[2] = 3;
+        indices[8] = 7;
+        indices[15] = 3;
+        fields.extend(bc7_indices(indices, 3, &[0, 15]));
+        let texels = decode_bc7(&bc7_block(1, &fields));
+        assert_eq!(texels[0], [255, 2, 2, 255]);
+        assert_eq!(texels[1], [2, 2, 255, 255]);
+        assert_eq!(texels[2], [148, 2, 109, 255]);
+        assert_eq!(texels[8], [253, 253, 253, 255]);
+        assert_eq!(texels[15], [107, 107, 107, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_2() {
+        // Partition 1 has its subset 1 anchor at texel 3 and its subset 2 anchor at texel 8.
+        let colors = [
+            [0, 0, 0],
+            [31, 31, 31],
+            [31, 0, 0],
+            [0, 31, 0],
+            [22, 22, 22],
+            [22, 22, 22],
+        ];
+        let mut fields = vec![(1, 6)];
+        fields.extend(bc7_endpoints(&colors, 5, &[], 0));
+        let mut indices = [0; 16];
+        indices[1] = 1;
+        indices[2] = 3;
+        indices[3] = 1;
+        indices[7] = 3;
+        indices[8] = 1;
+        fields.extend(bc7_indices(indices, 2, &[0, 3, 8]));
+        let texels = decode_bc7(&bc7_block(2, &fields));
+        assert_eq!(texels[0], [0, 0, 0, 255]);
+        assert_eq!(texels[1], [84, 84, 84, 255]);
+        assert_eq!(texels[2], [255, 255, 255, 255]);
+        assert_eq!(texels[3], [171, 84, 0, 255]);
+        assert_eq!(texels[7], [0, 255, 0, 255]);
+        assert_eq!(texels[8], [181, 181, 181, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_3() {
+        // Partition 34 alternates subsets in a checkerboard of pairs, anchored at texel 6.
+        let colors = [[100, 50, 0], [127, 127, 127], [0, 0, 0], [64, 64, 64]];
+        let mut fields = vec![(34, 6)];
+        fields.extend(bc7_endpoints(&colors, 7, &[], 0));
+        fields.extend([0, 1, 1, 0].iter().map(|&p_bit| (p_bit, 1)));
+        let mut indices = [0; 16];
+        indices[0] = 1;
+        indices[1] = 2;
+        indices[2] = 3;
+        indices[6] = 1;
+        fields.extend(bc7_indices(indices, 2, &[0, 6]));
+        let texels = decode_bc7(&bc7_block(3, &fields));
+        assert_eq!(texels[0], [218, 151, 84, 255]);
+        assert_eq!(texels[1], [86, 86, 86, 255]);
+        assert_eq!(texels[2], [255, 255, 255, 255]);
+        assert_eq!(texels[6], [43, 43, 43, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_4() {
+        // Rotation 1 swaps red and alpha, index selection 1 takes color from the 3-bit indices.
+        let mut fields = vec![(1, 2), (1, 1)];
+        fields.extend(bc7_endpoints(&[[22, 0, 31], [0, 31, 0]], 5, &[42, 63], 6));
+        let mut indices = [0; 16];
+        indices[0] = 1;
+        fields.extend(bc7_indices(indices, 2, &[0]));
+        let mut secondary_indices = [0; 16];
+        secondary_indices[0] = 3;
+        secondary_indices[1] = 7;
+        fields.extend(bc7_indices(secondary_indices, 3, &[0]));
+        let texels = decode_bc7(&bc7_block(4, &fields));
+        assert_eq!(texels[0], [198, 108, 147, 105]);
+        assert_eq!(texels[1], [170, 255, 0, 0]);
+    }
+
+    #[test]
+    fn bc7_mode_5() {
+        // Rotation 3 swaps blue and alpha, alpha always takes the second set of indices.
+        let mut fields = vec![(3, 2)];
+        fields.extend(bc7_endpoints(&[[127, 0, 64], [0, 127, 0]], 7, &[0, 200], 8));
+        let mut indices = [0; 16];
+        indices[0] = 1;
+        indices[1] = 3;
+        fields.extend(bc7_indices(indices, 2, &[0]));
+        let mut secondary_indices = [0; 16];
+        secondary_indices[0] = 1;
+        secondary_indices[1] = 2;
+        fields.extend(bc7_indices(secondary_indices, 2, &[0]));
+        let texels = decode_bc7(&bc7_block(5, &fields));
+        assert_eq!(texels[0], [171, 84, 66, 87]);
+        assert_eq!(texels[1], [0, 255, 134, 0]);
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        let mut fields = bc7_endpoints(&[[10, 20, 30], [127, 127, 127]], 7, &[40, 127], 7);
+        fields.extend(&[(1, 1), (0, 1)]);
+        let mut indices = [0; 16];
+        indices[1] = 15;
+        indices[2] = 5;
+        fields.extend(bc7_indices(indices, 4, &[0]));
+        let texels = decode_bc7(&bc7_block(6, &fields));
+        assert_eq!(texels[0], [21, 41, 61, 81]);
+        assert_eq!(texels[1], [254, 254, 254, 254]);
+        assert_eq!(texels[2], [97, 111, 124, 138]);
+    }
+
+    #[test]
+    fn bc7_mode_7() {
+        // Partition 0 puts the right two columns in subset 1.
+        let colors = [[31, 0, 0], [0, 0, 0], [16, 16, 16], [16, 16, 16]];
+        let mut fields = vec![(0, 6)];
+        fields.extend(bc7_endpoints(&colors, 5, &[31, 0, 16, 16], 5));
+        fields.extend([1, 0, 0, 1].iter().map(|&p_bit| (p_bit, 1)));
+        let mut indices = [0; 16];
+        indices[1] = 3;
+        indices[15] = 1;
+        fields.extend(bc7_indices(indices, 2, &[0, 15]));
+        let texels = decode_bc7(&bc7_block(7, &fields));
+        assert_eq!(texels[0], [255, 4, 4, 255]);
+        assert_eq!(texels[1], [0, 0, 0, 0]);
+        assert_eq!(texels[2], [130, 130, 130, 130]);
+        assert_eq!(texels[15], [131, 131, 131, 131]);
+    }
+
+    #[test]
+    fn bc7_reserved_mode_is_transparent_black() {
+        assert_eq!(decode_bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn bc7_anchors_are_in_their_subsets() {
+        for partition in 0..64 {
+            assert_eq!(bc7_subset(2, partition, 0), 0);
+            assert_eq!(
+                bc7_subset(2, partition, BC7_ANCHORS_2[partition] as usize),
+                1
+            );
+            assert_eq!(bc7_subset(3, partition, 0), 0);
+            let [second, third] = BC7_ANCHORS_3[partition];
+            assert_eq!(bc7_subset(3, partition, second as usize), 1);
+            assert_eq!(bc7_subset(3, partition, third as usize), 2);
+        }
+    }
+
+    #[test]
+    fn decode_crops_edge_blocks() {
+        // A 6x5 image needs 2x2 blocks, each a solid color picked by its first endpoint.
+        let colors: [u16; 4] = [0xf800, 0x07e0, 0x001f, 0xffff];
+        let data: Vec<u8> = colors
+            .iter()
+            .flat_map(|color| {
+                let [low, high] = color.to_le_bytes();
+                vec![low, high, 0, 0, 0, 0, 0, 0]
+            })
+            .collect();
+        let (format, texels) = decode(wgpu::TextureFormat::Bc1RgbaUnorm, 6, 5, &data).unwrap();
+        assert_eq!(format, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!(texels.len(), 6 * 5 * 4);
+        let texel = |x: usize, y: usize| &texels[(y * 6 + x) * 4..][..4];
+        assert_eq!(texel(3, 3), [255, 0, 0, 255]);
+        assert_eq!(texel(4, 0), [0, 255, 0, 255]);
+        assert_eq!(texel(0, 4), [0, 0, 255, 255]);
+        assert_eq!(texel(5, 4), [255, 255, 255, 255]);
+
+        let bc5 = decode(wgpu::TextureFormat::Bc5RgUnorm, 4, 4, &[0; 16]).unwrap();
+        assert_eq!(
+            (bc5.0, bc5.1.len()),
+            (wgpu::TextureFormat::Rg8Unorm, 4 * 4 * 2)
+        );
+        assert!(decode(wgpu::TextureFormat::Bc6hRgbUfloat, 4, 4, &[0; 16]).is_none());
+    }
+}
@@ -0,0 +1,177 @@
+//! KTX2 and DDS containers with pre-built mip chains.
+
+use super::{MipChain, TextureError};
+
+/// Bytes in a 2D mip level of `width` x `height` texels.
+pub(super) fn level_size(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+    let info = format.describe();
+    let (block_width, block_height) = info.block_dimensions;
+    let (block_width, block_height) = (block_width as u32, block_height as u32);
+    let blocks_wide = (width + block_width - 1) / block_width;
+    let blocks = blocks_wide * ((height + block_height - 1) / block_height);
+    blocks as usize * info.block_size as usize
+}
+
+fn unsupported(what: impl Into<String>) -> TextureError {
+    TextureError::UnsupportedFormat(what.into())
+}
+
+/// Rejects chains that go on past 1x1, which wgpu would fail to create.
+fn check_level_count(width: u32, height: u32, levels: usize) -> Result<(), TextureError> {
+    let max_levels = 32 - width.max(height).max(1).leading_zeros();
+    if levels > max_levels as usize {
+        return Err(TextureError::TooManyMipLevels(levels));
+    }
+    Ok(())
+}
+
+/// Cuts each level down to the first image, leaving out any further layers or faces.
+fn split_levels<'a>(
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    levels: impl Iterator<Item = &'a [u8]>,
+) -> Option<Vec<Vec<u8>>> {
+    levels
+        .enumerate()
+        .map(|(level, data)| {
+            let size = level_size(format, (width >> level).max(1), (height >> level).max(1));
+            data.get(..size).map(<[u8]>::to_vec)
+        })
+        .collect()
+}
+
+pub(super) fn load_ktx2(bytes: &[u8]) -> Result<MipChain, TextureError> {
+    use ktx2::Format;
+    use wgpu::TextureFormat::*;
+
+    let reader = ktx2::Reader::new(bytes)?;
+    let header = reader.header();
+    if let Some(scheme) = header.supercompression_scheme {
+        return Err(unsupported(format!("KTX2 supercompression {:?}", scheme)));
+    }
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        return Err(unsupported("KTX2 volume, array or cube texture"));
+    }
+
+    let format = match header.format {
+        Some(Format::BC1_RGB_UNORM_BLOCK) | Some(Format::BC1_RGBA_UNORM_BLOCK) => Bc1RgbaUnorm,
+        Some(Format::BC1_RGB_SRGB_BLOCK) | Some(Format::BC1_RGBA_SRGB_BLOCK) => Bc1RgbaUnormSrgb,
+        Some(Format::BC3_UNORM_BLOCK) => Bc3RgbaUnorm,
+        Some(Format::BC3_SRGB_BLOCK) => Bc3RgbaUnormSrgb,
+        Some(Format::BC5_UNORM_BLOCK) => Bc5RgUnorm,
+        Some(Format::BC7_UNORM_BLOCK) => Bc7RgbaUnorm,
+        Some(Format::BC7_SRGB_BLOCK) => Bc7RgbaUnormSrgb,
+        Some(Format::R8G8B8A8_UNORM) => Rgba8Unorm,
+        Some(Format::R8G8B8A8_SRGB) => Rgba8UnormSrgb,
+        other => return Err(unsupported(format!("KTX2 format {:?}", other))),
+    };
+
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+    check_level_count(width, height, reader.levels().count())?;
+    let levels = split_levels(format, width, height, reader.levels())
+        .ok_or(TextureError::Ktx2(ktx2::ParseError::UnexpectedEnd))?;
+    Ok(MipChain {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+pub(super) fn load_dds(bytes: &[u8]) -> Result<MipChain, TextureError> {
+    use ddsfile::{D3DFormat, DxgiFormat};
+    use wgpu::TextureFormat::*;
+
+    let dds = ddsfile::Dds::read(bytes)?;
+    if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+        return Err(unsupported("DDS volume, array or cube texture"));
+    }
+
+    // Legacy headers don't say whether the data is sRGB, so it is read as linear.
+    let format = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+        (Some(DxgiFormat::BC1_UNorm), _) | (None, Some(D3DFormat::DXT1)) => Bc1RgbaUnorm,
+        (Some(DxgiFormat::BC1_UNorm_sRGB), _) => Bc1RgbaUnormSrgb,
+        (Some(DxgiFormat::BC3_UNorm), _) | (None, Some(D3DFormat::DXT5)) => Bc3RgbaUnorm,
+        (Some(DxgiFormat::BC3_UNorm_sRGB), _) => Bc3RgbaUnormSrgb,
+        (Some(DxgiFormat::BC5_UNorm), _) => Bc5RgUnorm,
+        (Some(DxgiFormat::BC7_UNorm), _) => Bc7RgbaUnorm,
+        (Some(DxgiFormat::BC7_UNorm_sRGB), _) => Bc7RgbaUnormSrgb,
+        (Some(DxgiFormat::R8G8B8A8_UNorm), _) | (None, Some(D3DFormat::A8B8G8R8)) => Rgba8Unorm,
+        (Some(DxgiFormat::R8G8B8A8_UNorm_sRGB), _) => Rgba8UnormSrgb,
+        (Some(DxgiFormat::B8G8R8A8_UNorm), _) | (None, Some(D3DFormat::A8R8G8B8)) => Bgra8Unorm,
+        (Some(DxgiFormat::B8G8R8A8_UNorm_sRGB), _) => Bgra8UnormSrgb,
+        (Some(other), _) => return Err(unsupported(format!("DDS format {:?}", other))),
+        (None, other) => return Err(unsupported(format!("DDS format {:?}", other))),
+    };
+
+    let (width, height) = (dds.get_width(), dds.get_height());
+    // Some exporters set the mip count flag with a count of 0 for a single level.
+    let level_count = dds.get_num_mipmap_levels().max(1);
+    check_level_count(width, height, level_count as usize)?;
+    // The single layer starts the data. `get_data` would size it by the raw count instead.
+    let data = &dds.data;
+    let mut offset = 0;
+    let chunks = (0..level_count).map(|level| {
+        let size = level_size(format, (width >> level).max(1), (height >> level).max(1));
+        let chunk = data.get(offset..).unwrap_or_default();
+        offset += size;
+        chunk
+    });
+    let levels = split_levels(format, width, height, chunks)
+        .ok_or(TextureError::Dds(ddsfile::Error::ShortFile))?;
+    Ok(MipChain {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dds(width: u32, height: u32, mip_map_count: u32) -> Vec<u8> {
+        let mut dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height,
+            width,
+            depth: None,
+            format: ddsfile::DxgiFormat::R8G8B8A8_UNorm,
+            // More than one level sets the mip count flag.
+            mipmap_levels: Some(2),
+            array_layers: None,
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+            alpha_mode: ddsfile::AlphaMode::Unknown,
+        })
+        .unwrap();
+        dds.header.mip_map_count = Some(mip_map_count);
+        // Enough data for a full chain, 4 bytes a texel.
+        dds.data = vec![0; (width * height * 2 * 4) as usize];
+        let mut bytes = Vec::new();
+        dds.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn dds_with_zero_mip_count_has_one_level() {
+        let chain = load_dds(&dds(4, 2, 0)).unwrap();
+        assert_eq!(chain.levels.len(), 1);
+        assert_eq!(chain.levels[0].len(), 4 * 2 * 4);
+    }
+
+    #[test]
+    fn mip_chains_stop_at_one_texel() {
+        assert_eq!(load_dds(&dds(4, 2, 3)).unwrap().levels.len(), 3);
+        assert!(matches!(
+            load_dds(&dds(4, 2, 4)),
+            Err(TextureError::TooManyMipLevels(4))
+        ));
+        assert!(check_level_count(1, 1, 1).is_ok());
+        assert!(check_level_count(1, 1, 2).is_err());
+        assert!(check_level_count(5, 1, 3).is_ok());
+        assert!(check_level_count(5, 1, 4).is_err());
+    }
+}
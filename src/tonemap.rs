@@ -0,0 +1,208 @@
+use serde::Deserialize;
+use std::{collections::HashSet, path::PathBuf};
+
+use crate::{
+    pipeline::PipelineBuilder,
+    reflect::{self, UniformBlock},
+    Dependencies, PassId, PipelineHandler, State, TargetId,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum ToneMappingOperator {
+    Reinhard,
+    Aces,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ToneMapping {
+    pub operator: ToneMappingOperator,
+    /// Scale applied to the HDR color before the operator.
+    pub exposure: f32,
+    /// Applied after the operator, on top of the sRGB encoding of the swap chain.
+    pub gamma: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMappingOperator::Aces,
+            exposure: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+// Matches the `ToneMapping` block in tonemap.frag.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ToneMappingUniform {
+    exposure: f32,
+    gamma: f32,
+    operator: u32,
+    encode_srgb: u32,
+}
+
+impl ToneMappingUniform {
+    /// Offsets of the `ToneMapping` block members.
+    fn members() -> Vec<(&'static str, u32)> {
+        let uniform: Self = bytemuck::Zeroable::zeroed();
+        vec![
+            (
+                "u_exposure",
+                reflect::member_offset(&uniform, &uniform.exposure),
+            ),
+            ("u_gamma", reflect::member_offset(&uniform, &uniform.gamma)),
+            (
+                "u_operator",
+                reflect::member_offset(&uniform, &uniform.operator),
+            ),
+            (
+                "u_encode_srgb",
+                reflect::member_offset(&uniform, &uniform.encode_srgb),
+            ),
+        ]
+    }
+}
+
+impl ToneMapping {
+    pub(crate) fn to_uniform(self, target_format: wgpu::TextureFormat) -> ToneMappingUniform {
+        ToneMappingUniform {
+            exposure: self.exposure,
+            gamma: self.gamma,
+            operator: match self.operator {
+                ToneMappingOperator::Reinhard => 0,
+                ToneMappingOperator::Aces => 1,
+            },
+            encode_srgb: !target_format.describe().srgb as u32,
+        }
+    }
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("tonemap_bind_group_layout"),
+    })
+}
+
+fn create_bundle(
+    state: &State,
+    bind_group_layout: &wgpu::BindGroupLayout,
+) -> Option<wgpu::RenderBundle> {
+    let hdr = state.render_graph().target(TargetId::HDR)?;
+    let bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&hdr.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&hdr.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: state.tone_mapping_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("tonemap_bind_group"),
+    });
+
+    let formats = state.pass_formats(PassId::TONEMAP)?;
+    let uniform_block =
+        UniformBlock::of::<ToneMappingUniform>(0, 2).with_members(ToneMappingUniform::members());
+    let pipeline = PipelineBuilder::new("assets/shaders/fullscreen.vert")
+        .with_fragment_shader("assets/shaders/tonemap.frag")
+        .with_formats(&formats)
+        .with_bind_group_layouts(&[bind_group_layout])
+        .with_uniform_blocks(&[uniform_block])
+        .build(&state.device);
+    let pipeline = state.report_errors(pipeline)?;
+
+    let mut encoder = formats.create_bundle_encoder(&state.device, None);
+
+    encoder.set_pipeline(&pipeline);
+    encoder.set_bind_group(0, &bind_group, &[]);
+    encoder.draw(0..3, 0..1);
+
+    Some(encoder.finish(&wgpu::RenderBundleDescriptor {
+        label: Some("tonemap"),
+    }))
+}
+
+/// Draws the HDR scene into the swap chain with the tone mapping settings of `State`.
+pub struct TonemapPipeline {
+    bind_group_layout: wgpu::BindGroupLayout,
+    render_bundle: Option<wgpu::RenderBundle>,
+}
+
+impl TonemapPipeline {
+    pub fn create(state: &State) -> Self {
+        let bind_group_layout = create_bind_group_layout(&state.device);
+        let render_bundle = create_bundle(state, &bind_group_layout);
+        Self {
+            bind_group_layout,
+            render_bundle,
+        }
+    }
+}
+
+impl PipelineHandler for TonemapPipeline {
+    fn passes(&self) -> &[PassId] {
+        &[PassId::TONEMAP]
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        Dependencies::default()
+            .with_shader("assets/shaders/fullscreen.vert")
+            .with_shader("assets/shaders/tonemap.frag")
+    }
+
+    fn files_changed(&mut self, state: &mut State, _changed: &HashSet<PathBuf>) {
+        let bundle = create_bundle(state, &self.bind_group_layout);
+        if bundle.is_some() {
+            self.render_bundle = bundle;
+            log::info!("Tonemap bundle reloaded.");
+        }
+    }
+
+    fn resized(&mut self, state: &State) {
+        self.render_bundle = create_bundle(state, &self.bind_group_layout);
+    }
+
+    fn render_bundle(&mut self, _state: &State, _pass: PassId) -> Option<&wgpu::RenderBundle> {
+        self.render_bundle.as_ref()
+    }
+}